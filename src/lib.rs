@@ -5,18 +5,77 @@
 //! ## Usage
 //!
 //! You need to import [`Database`], [`Config`], and a trait ([`Synchronous`], [`Asynchronous`]).
-//! Then initialize [`Database::new()`] with [`Config::new()`] then database will give you function in either synchronously or asynchronously based on trait you imported in to the scope.
+//! Then initialize [`Database::new()`] with [`Config::new()`] (or a custom URL like `Database::new("https://...")`) then database will give you function in either synchronously or asynchronously based on trait you imported in to the scope.
 //!
 //! ## Possible Exceptions
 //!
 //! [`Error`] struct contain useful informations and both [`std::fmt::Display`] and [`std::error::Error`] (support "?").
 //! Also there's different error kinds that can happens, here's the list of them.
 //! - [`ErrorKind::HttpError`]
-//!     Raised when there's something wrong when doing HTTP request.
+//!   Raised when there's something wrong when doing HTTP request.
 //! - [`ErrorKind::NoItemFoundError`]
-//!     Raised when item is not found
+//!   Raised when item is not found
 //! - [`ErrorKind::DecodeError`]
-//!     Raised when the key name is undecodable to UTF-8 string.
+//!   Raised when the key name is undecodable to UTF-8 string.
+//! - [`ErrorKind::JsonError`]
+//!   Raised when JSON couldn't be serialized or deserialized. (`serde` feature)
+//! - [`ErrorKind::InvalidKeyError`]
+//!   Raised when the key is rejected locally (e.g. longer than [`Config::with_max_key_length`]).
+//! - [`ErrorKind::InvalidPrefixError`]
+//!   Raised when the prefix is empty while it's required (See [`Config::with_require_prefix`]).
+//! - [`ErrorKind::ValidationError`]
+//!   Raised when the validator passed to `set_json` rejects the value. (`serde` feature)
+//! - [`ErrorKind::InvalidValueError`]
+//!   Raised when the value is rejected locally (See [`Config::with_reject_control_chars_in_values`]).
+//! - [`ErrorKind::ValueTooLargeError`]
+//!   Raised when the value is larger than [`Config::with_max_value_bytes`] (or a `set_many` pair can't fit in [`Config::with_max_body_bytes`]).
+//! - [`ErrorKind::ParseError`]
+//!   Raised when the value couldn't be parsed by `get_parsed`.
+//! - [`ErrorKind::ConsistencyError`]
+//!   Raised when the database still doesn't reflect a write after retrying (e.g. `delete_verified`).
+//! - [`ErrorKind::TimeoutError`]
+//!   Raised when waiting for something gave up after its timeout (e.g. `measure_write_visibility`).
+//! - [`ErrorKind::CircuitOpenError`]
+//!   Raised when the request wasn't sent as the key kept failing (See [`Config::with_circuit_breaker`]).
+//! - [`ErrorKind::SinkError`]
+//!   Raised when the sink passed to `list_into_sink` failed to take a key.
+//! - [`ErrorKind::ReservedValueError`]
+//!   Raised when a plain write's value collides with a reserved value format (See [Reserved prefixes](#reserved-prefixes)).
+//! - [`ErrorKind::InvalidUrlError`]
+//!   Raised when the database URL is malformed (See [`Config::try_new_custom_url`]).
+//! - [`ErrorKind::InvalidArgumentError`]
+//!   Raised when an argument is out of range (e.g. `list_page`'s `page_size` of 0).
+//!
+//! ## Features
+//!
+//! - `serde`
+//!   Enables JSON helpers like `export_json` and `import_json` for backing up the database.
+//! - `cache`
+//!   Enables in-memory caching helpers like `get_swr`. (Asynchronous refreshes are spawned on Tokio runtime, this pulls in `tokio`)
+//! - `encoding`
+//!   Enables `get_with_encoding` for reading values that aren't UTF-8 (e.g. legacy Latin-1 data).
+//! - `compression`
+//!   Enables `set_compressed` for storing gzip compressed values and `Config::with_auto_decompress` for reading them back transparently.
+//! - `tower`
+//!   Enables `HealthService`, a `tower::Service` reporting the database's health for `/healthz` routes (e.g. in axum).
+//! - `blocking-adapter`
+//!   Enables `BlockingAdapter` for calling [`Synchronous`] methods from async code without stalling the Tokio runtime. (Pulls in `tokio`)
+//!
+//! ## Reserved prefixes
+//!
+//! - Keys under [`RESERVED_PREFIX`] are used for this crate's own bookkeeping (`bench`, `measure_write_visibility` and `set_idempotent`'s sidecar keys).
+//!   They aren't rejected, but writing there yourself could break those helpers.
+//! - Values starting with `COMPRESSED_VALUE_PREFIX` are reserved for `set_compressed` (`compression` feature).
+//!   With `Config::with_auto_decompress`, plain writes (`set`, `set_many` and everything built on them) of a value that would be decompressed when read back are rejected
+//!   with [`ErrorKind::ReservedValueError`], so it can't turn them into something else. Store such values with `set_compressed` instead.
+//!   Without it nothing is decompressed and they're written as-is, so another [`Database`] reading them with it enabled would still decompress them.
+//!   Values copied as-is by `clone_namespace`, `copy_all_to`, `import_json` and `with_snapshot`'s rollback aren't checked, so compressed values survive them.
+//!
+//! ## Shutdown
+//!
+//! Writes aren't buffered, every `set` and `delete` is sent right away and is done once it returns.
+//! So there's nothing to flush when the process is terminated (SIGTERM/SIGINT), only requests that are still in flight may be lost.
+//! The `cache` feature only caches reads, dropping it loses nothing.
 //!
 //! ## Examples
 //!
@@ -57,20 +116,73 @@
 //! }
 //! ```
 
+#[allow(clippy::single_component_path_imports)]
 use async_trait;
+use futures::SinkExt;
+use futures::StreamExt;
+#[allow(clippy::single_component_path_imports)]
 use reqwest;
+#[allow(clippy::single_component_path_imports)]
 use std;
+#[allow(clippy::single_component_path_imports)]
 use urlencoding;
 
 /// This constant is for storing replit's db's domain name. This would likely change by whatever the reason is.
+#[allow(dead_code)]
 const MAIN_DOMAIN: &str = "kv.replit.com";
 
+/// Replit's documented limit for key's length in bytes.
+pub const REPLIT_MAX_KEY_LENGTH: usize = 1000;
+
+/// Replit's documented limit for value's size in bytes (5 MiB). The whole database is also limited to 50 MiB.
+/// Going over it makes server respond with 413, use [`Config::with_max_value_bytes`] to catch it before sending instead.
+pub const REPLIT_MAX_VALUE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Prefix of keys reserved for this crate's own bookkeeping (e.g. [`Synchronous::bench`]'s keys).
+pub const RESERVED_PREFIX: &str = "__replit_db__/";
+
+/// Delay between retries, multiplied by the attempt's number.
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Maximum requests in flight for bulk operations of [`Asynchronous`].
+const MAX_CONCURRENCY: usize = 8;
+
+/// How often `measure_write_visibility` polls for its write.
+const VISIBILITY_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Most bytes a plain write's value is decompressed to when checking it for [`ErrorKind::ReservedValueError`]. (`compression` feature)
+#[cfg(feature = "compression")]
+const MAX_CHECKED_DECOMPRESSED_BYTES: usize = 64 * 1024 * 1024;
+
+/// Maximum failing keys tracked by [`Config::with_circuit_breaker`], so keys that fail once and are never used again don't pile up.
+const MAX_CIRCUITS: usize = 1024;
+
+/// Validator for `set_json`, returning [`Err`] with a message to reject the value. (`serde` feature)
+#[cfg(feature = "serde")]
+pub type JsonValidator<V> = fn(&V) -> Result<(), String>;
+
+/// Start of every value written by `set_compressed`. (gzip's magic bytes, base64 encoded)
+/// Plain writes of values that would be decompressed by [`Config::with_auto_decompress`] are rejected with [`ErrorKind::ReservedValueError`]. (`compression` feature)
+#[cfg(feature = "compression")]
+pub const COMPRESSED_VALUE_PREFIX: &str = "H4sI";
+
 /// This type is a shorthand for [`Option<&str>::None`] or [`None::<&str>`].
 pub const NONE: Option<&str> = None;
 
 /// Configuration struct that contains information needed for Database.
 pub struct Config {
     url: String,
+    max_key_length: usize,
+    reject_control_chars_in_values: bool,
+    max_value_bytes: Option<usize>,
+    require_prefix: bool,
+    max_concurrent_requests: Option<usize>,
+    trim_trailing_newline: bool,
+    sanitize_keys: Option<fn(&str) -> String>,
+    max_body_bytes: Option<usize>,
+    circuit_breaker: Option<(usize, std::time::Duration)>,
+    #[cfg(feature = "compression")]
+    auto_decompress: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -82,6 +194,34 @@ pub enum ErrorKind {
     NoItemFoundError,
     /// Couldn't decode bytes to string UTF-8.
     DecodeError,
+    /// Couldn't serialize or deserialize JSON.
+    JsonError,
+    /// Key is invalid (e.g. too long) and got rejected before sending the request.
+    InvalidKeyError,
+    /// Prefix is empty while [`Config::with_require_prefix`] is enabled.
+    InvalidPrefixError,
+    /// Value got rejected by a validator before sending the request.
+    ValidationError,
+    /// Value is invalid (e.g. contains control characters) and got rejected before sending the request.
+    InvalidValueError,
+    /// Value is larger than [`Config::with_max_value_bytes`]. (or a `set_many` pair can't fit in [`Config::with_max_body_bytes`])
+    ValueTooLargeError,
+    /// Value couldn't be parsed with [`std::str::FromStr`].
+    ParseError,
+    /// The database still doesn't reflect a write after retrying.
+    ConsistencyError,
+    /// Waiting for something gave up after its timeout.
+    TimeoutError,
+    /// The key kept failing and its circuit is open, the request wasn't sent. (See [`Config::with_circuit_breaker`])
+    CircuitOpenError,
+    /// The [`futures::Sink`] passed to [`Asynchronous::list_into_sink`] failed.
+    SinkError,
+    /// Value collides with a format reserved for this crate (e.g. `set_compressed`'s values while `Config::with_auto_decompress` is enabled) and got rejected before sending the request.
+    ReservedValueError,
+    /// Database URL isn't a valid `http`/`https` URL. (See [`Config::try_new_custom_url`])
+    InvalidUrlError,
+    /// An argument is out of range (e.g. a `list_page` size of 0) and got rejected before sending the request.
+    InvalidArgumentError,
 }
 
 #[derive(Debug, Clone)]
@@ -93,44 +233,594 @@ pub struct Error {
     pub message: String,
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Nested tree of keys from [`Synchronous::list_tree`] and [`Asynchronous::list_tree`].
+/// Each level is a segment of the key split by the delimiter, the root is the listed prefix.
+pub struct TreeNode {
+    /// Full key if a key ends at this node. (A node can be both a key and have children)
+    pub key: Option<String>,
+    /// Child nodes by their segment.
+    pub children: std::collections::BTreeMap<String, TreeNode>,
+}
+
+#[derive(Debug, Clone)]
+/// Receipt of a write from [`Synchronous::set_with_receipt`] and [`Asynchronous::set_with_receipt`].
+pub struct WriteReceipt {
+    /// Key that got written. (After [`Config::with_sanitize_keys`])
+    pub key: String,
+    /// Value's size in bytes.
+    pub bytes: usize,
+    /// When the write was done.
+    pub at: std::time::SystemTime,
+}
+
+#[derive(Debug, Clone)]
+/// Value with its metadata from [`Synchronous::get_with_metadata`] and [`Asynchronous::get_with_metadata`].
+pub struct ValueWithMetadata {
+    /// Value of the key.
+    pub value: String,
+    /// When the value was last written, if the server sent `Last-Modified` header.
+    pub last_modified: Option<std::time::SystemTime>,
+    /// `ETag` header, if the server sent it.
+    pub etag: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Result of [`Synchronous::ping`] and [`Asynchronous::ping`].
+pub enum PingStatus {
+    /// Database responded successfully.
+    Reachable,
+    /// Database responded but rejected the URL (401/403). Likely the URL got rotated.
+    Unauthorized,
+    /// Couldn't connect to the database or it responded with an unexpected status.
+    Unreachable,
+}
+
+#[derive(Debug, Clone)]
+/// Result of [`Synchronous::bench`] and [`Asynchronous::bench`].
+pub struct BenchResult {
+    /// Operations performed.
+    pub ops: usize,
+    /// Operations that failed.
+    pub errors: usize,
+    /// Time took to perform every operations (cleanup is not included).
+    pub elapsed: std::time::Duration,
+    /// Operations per second.
+    pub ops_per_sec: f64,
+    /// Ratio of failed operations. (0.0 to 1.0)
+    pub error_rate: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Result of [`Synchronous::consistency_check`] and [`Asynchronous::consistency_check`].
+pub struct ConsistencyReport {
+    /// How many keys the listing returned.
+    pub listed: usize,
+    /// Keys returned by the listing but not found when getting them. (Sorted)
+    pub missing_on_get: std::vec::Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Result of [`Synchronous::list_checked`] and [`Asynchronous::list_checked`].
+pub struct ListResult {
+    /// Listed keys.
+    pub keys: std::vec::Vec<String>,
+    /// Whether the server explicitly indicated an empty listing with a `204` status.
+    /// `false` for an empty `200` body (whatever its `Content-Length`) and whenever `keys` isn't empty.
+    /// Replit's server answers an empty listing with an empty `200`, so against it this is always `false` and emptiness is always ambiguous.
+    pub explicit_empty: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Position in a paginated listing from [`Synchronous::list_page`] and [`Asynchronous::list_page`].
+/// Save it with [`ListCursor::to_token`] and load it back with [`ListCursor::from_token`] to resume a scan (e.g. after a crash).
+pub struct ListCursor {
+    last_key: String,
+}
+
+/// Kind of request passed to hooks registered with [`Database::with_operation_hook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Setting a key.
+    Set,
+    /// Getting a key's value.
+    Get,
+    /// Checking a key's size without downloading the value.
+    Head,
+    /// Deleting a key.
+    Delete,
+    /// Listing keys by prefix. (Also used by `ping`)
+    List,
+}
+
+/// Closure called before a request. (See [`Database::with_operation_hook`])
+type BeforeHook = std::boxed::Box<dyn Fn(Operation, &str) + Send + Sync>;
+/// Closure called after a request. (See [`Database::with_operation_hook`])
+type AfterHook = std::boxed::Box<dyn Fn(Operation, &str, Option<u16>) + Send + Sync>;
+
+/// Slot of [`Config::with_max_concurrent_requests`] taken by a request, held until its response's body is read. ([`None`] without a limit)
+type RequestPermit = Option<LimiterPermit>;
+
+/// Free slots of [`Config::with_max_concurrent_requests`], shared by blocking and async requests without needing a particular runtime.
+struct Limiter {
+    state: std::sync::Mutex<LimiterState>,
+    freed: std::sync::Condvar,
+}
+
+struct LimiterState {
+    available: usize,
+    /// Async requests waiting for a slot, all woken up when one is freed.
+    wakers: std::vec::Vec<std::task::Waker>,
+}
+
+/// Slot taken from a [`Limiter`], given back when dropped.
+struct LimiterPermit {
+    limiter: std::sync::Arc<Limiter>,
+}
+
+#[allow(clippy::needless_return)]
+impl Limiter {
+    fn new(max: usize) -> Self {
+        return Limiter {
+            state: std::sync::Mutex::new(LimiterState {
+                available: max,
+                wakers: std::vec::Vec::new(),
+            }),
+            freed: std::sync::Condvar::new(),
+        };
+    }
+
+    /// Take a slot, blocking the thread until one is free.
+    fn acquire_blocking(self: &std::sync::Arc<Self>) -> LimiterPermit {
+        let mut state = self.state.lock().unwrap();
+        while state.available == 0 {
+            state = self.freed.wait(state).unwrap();
+        }
+        state.available -= 1;
+        return LimiterPermit {
+            limiter: self.clone(),
+        };
+    }
+
+    /// Take a slot, waiting until one is free.
+    async fn acquire(self: &std::sync::Arc<Self>) -> LimiterPermit {
+        return futures::future::poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+            if state.available == 0 {
+                state.wakers.push(cx.waker().clone());
+                return std::task::Poll::Pending;
+            }
+            state.available -= 1;
+            return std::task::Poll::Ready(LimiterPermit {
+                limiter: self.clone(),
+            });
+        })
+        .await;
+    }
+}
+
+impl Drop for LimiterPermit {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        state.available += 1;
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+        self.limiter.freed.notify_one();
+    }
+}
+
+/// Hook closures called before and after every request.
+struct OperationHook {
+    before: BeforeHook,
+    after: AfterHook,
+}
+
 /// Database main struct.
 /// Please use this database with traits. (Availables are [`Synchronous`] and [`Asynchronous`])
+/// Cloning is cheap, clones share the same configuration (and cache).
+#[derive(Clone)]
 pub struct Database {
-    config: Config,
+    config: std::sync::Arc<Config>,
+    hooks: std::vec::Vec<std::sync::Arc<OperationHook>>,
+    limiter: Option<std::sync::Arc<Limiter>>,
+    circuits: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, Circuit>>>,
+    #[cfg(feature = "cache")]
+    cache: std::sync::Arc<std::sync::Mutex<Cache>>,
+}
+
+/// Consecutive failures of a key. (See [`Config::with_circuit_breaker`])
+struct Circuit {
+    failures: usize,
+    opened_at: Option<std::time::Instant>,
+    last_failure: std::time::Instant,
 }
 
+/// Cached values, along with the keys being fetched to be cached. (`cache` feature)
+#[cfg(feature = "cache")]
+#[derive(Default)]
+struct Cache {
+    entries: std::collections::HashMap<String, CacheEntry>,
+    fetching: std::collections::HashMap<String, CacheFetch>,
+}
+
+/// Cached value of a key. (`cache` feature)
+#[cfg(feature = "cache")]
+struct CacheEntry {
+    value: std::sync::Arc<str>,
+    fetched_at: std::time::Instant,
+    refreshing: bool,
+}
+
+/// Fetches of a key running to be cached, and how many times the key got invalidated since the first one started. (`cache` feature)
+/// Only kept while a fetch is running, so written keys that nobody reads don't pile up.
+#[cfg(feature = "cache")]
+struct CacheFetch {
+    running: usize,
+    invalidations: u64,
+}
+
+/// Runs [`Synchronous`] methods on Tokio's blocking thread pool (`tokio::task::spawn_blocking`), so they can be awaited from async code
+/// without stalling the runtime. Must be used inside Tokio runtime. (`blocking-adapter` feature)
+/// Get one with [`Database::blocking_adapter`].
+#[cfg(feature = "blocking-adapter")]
+#[derive(Clone)]
+pub struct BlockingAdapter {
+    db: Database,
+}
+
+/// [`tower_service::Service`] reporting the database's health with [`Asynchronous::ping`], for wiring `/healthz` routes. (`tower` feature)
+/// Any request is accepted (and ignored), it responds with [`PingStatus`] and never fails. Must be used inside Tokio runtime.
+/// Get one with [`Database::health_service`].
+#[cfg(feature = "tower")]
+#[derive(Clone)]
+pub struct HealthService {
+    db: Database,
+}
+
+/// Future returned by [`HealthService`]. (`tower` feature)
+#[cfg(feature = "tower")]
+type HealthFuture = std::pin::Pin<
+    std::boxed::Box<
+        dyn std::future::Future<Output = Result<PingStatus, std::convert::Infallible>> + Send,
+    >,
+>;
+
 /// Synchronous support for Database struct. Use this trait by import it then use it right away!
 pub trait Synchronous {
     /// Set a variable. `key` and `value` MUST implement [`AsRef<str>`]. ([`str`] and [`String`] implemented this.).
-    /// Possible Exception is [`ErrorKind::HttpError`] for HttpError
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key, [`ErrorKind::InvalidValueError`] for rejected value, [`ErrorKind::ValueTooLargeError`] for value over the limit, [`ErrorKind::ReservedValueError`] for value colliding with a reserved format
     fn set(&self, key: impl AsRef<str>, value: impl AsRef<str>) -> Result<(), Error>;
+    /// Same as [`Synchronous::set`] but returns a [`WriteReceipt`] of the write. Useful for audit logs.
+    fn set_with_receipt(
+        &self,
+        key: impl AsRef<str>,
+        value: impl AsRef<str>,
+    ) -> Result<WriteReceipt, Error>;
+    /// Same as [`Synchronous::set`] but skipped if a write with the same `idempotency_key` was already applied, for retrying without writing twice.
+    /// Applied idempotency keys are recorded in a sidecar key under `__replit_db__/idempotency/` which is never cleaned up by itself, so each one costs a key
+    /// (remove them with [`Synchronous::delete_prefix`] once retries can't happen anymore). Returns whether the value was written. [`None`] always writes.
+    /// It's best-effort, two writes racing with the same idempotency key could both be applied.
+    /// Possible Exceptions are the same as [`Synchronous::set`]
+    fn set_idempotent(
+        &self,
+        key: impl AsRef<str>,
+        value: impl AsRef<str>,
+        idempotency_key: Option<&str>,
+    ) -> Result<bool, Error>;
+    /// Same as [`Synchronous::set`] but the value can be anything that implements [`std::fmt::Display`] (e.g. numbers), it's formatted before being stored.
+    /// Possible Exceptions are the same as [`Synchronous::set`]
+    fn set_display<V: std::fmt::Display>(
+        &self,
+        key: impl AsRef<str>,
+        value: V,
+    ) -> Result<(), Error>;
+    /// Same as [`Synchronous::set`] but the value is gzip compressed then base64 encoded before being stored. Useful for large, repetitive values. (`compression` feature)
+    /// Read it back with [`Config::with_auto_decompress`] enabled.
+    /// Possible Exceptions are the same as [`Synchronous::set`] (size limit applies to the compressed value)
+    #[cfg(feature = "compression")]
+    fn set_compressed(&self, key: impl AsRef<str>, value: impl AsRef<str>) -> Result<(), Error>;
     /// Get a variable you just set. `key` MUST implement [`AsRef<str>`]. ([`str`] and [`String`] implemented this.).
-    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::NoItemFoundError`] for no items were found in the database
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::NoItemFoundError`] for no items were found in the database, [`ErrorKind::InvalidKeyError`] for over-long key, [`ErrorKind::ValueTooLargeError`] for value over the limit
     fn get(&self, key: impl AsRef<str>) -> Result<String, Error>;
+    /// Like [`Synchronous::get`] but retries up to `max_retries` times (with increasing delay) while the value is empty.
+    /// This is a heuristic for keys you know never hold an empty value, as the server could rarely give out an empty body for them.
+    /// A genuinely empty value is still returned after the retries are used up, the bound is what keeps it from retrying forever.
+    /// Possible Exceptions are the same as [`Synchronous::get`]
+    fn get_nonempty_retry(&self, key: impl AsRef<str>, max_retries: usize)
+        -> Result<String, Error>;
+    /// Same as [`Synchronous::get`] but parses the value with [`std::str::FromStr`]. (e.g. `db.get_parsed::<i64>("counter")`)
+    /// Possible Exceptions are the same as [`Synchronous::get`] and [`ErrorKind::ParseError`] for unparsable value
+    fn get_parsed<V: std::str::FromStr>(&self, key: impl AsRef<str>) -> Result<V, Error>
+    where
+        V::Err: std::fmt::Display;
     /// Delete a variable you just set. MUST implement [`AsRef<str>`]. ([`str`] and [`String`] implemented this.).
-    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::NoItemFoundError`] for no items were found in the database
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::NoItemFoundError`] for no items were found in the database, [`ErrorKind::InvalidKeyError`] for over-long key
     fn delete(&self, key: impl AsRef<str>) -> Result<(), Error>;
+    /// Stronger variant of [`Synchronous::delete`], after deleting it checks with [`Synchronous::exists`] that the key is really gone.
+    /// Checks again up to `max_retries` times (with increasing delay) while the key still shows up.
+    /// Possible Exceptions are the same as [`Synchronous::delete`] and [`ErrorKind::ConsistencyError`] for key that's still there after the retries
+    fn delete_verified(&self, key: impl AsRef<str>, max_retries: usize) -> Result<(), Error>;
     /// List variables. Optionally finding variable that contains defined prefix by passing [`Some`] with anything that implements [`AsRef<str>`]. ([`str`] and [`String`] implemented this.) or [`NONE`].
     /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
     fn list(&self, prefix: Option<impl AsRef<str>>) -> Result<std::vec::Vec<String>, Error>;
+    /// List variables whose name starts with `prefix`, ignoring case. (e.g. `user` matches `User1` and `USER2`)
+    /// The server's prefix matching is case-sensitive, so this lists EVERY key in the database and filters them locally.
+    /// It's a full scan, avoid it on large databases or in hot paths.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidPrefixError`] for empty prefix while it's required.
+    fn list_prefix_ci(&self, prefix: impl AsRef<str>) -> Result<std::vec::Vec<String>, Error>;
+    /// List a page of up to `page_size` variables (optionally with defined prefix) in key order, starting after `cursor`.
+    /// Returns the cursor of the next page, or [`None`] on the last page. Keys written behind the cursor while scanning won't show up.
+    /// Replit doesn't paginate listings, so every page lists every matching key and cuts the page out locally.
+    /// Paging through N keys downloads about N²/`page_size` keys in total, use [`Synchronous::list`] instead to read all of them.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidArgumentError`] for `page_size` of 0.
+    fn list_page(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        cursor: Option<&ListCursor>,
+        page_size: usize,
+    ) -> Result<(std::vec::Vec<String>, Option<ListCursor>), Error>;
+    /// List variables (optionally with defined prefix) into a bounded channel, sending each key as soon as it's read from the response.
+    /// Blocks while the channel is full (backpressure). Stops early without an error if the receiver got dropped.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    fn list_to_sender(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        tx: std::sync::mpsc::SyncSender<String>,
+    ) -> Result<(), Error>;
+    /// List variables (optionally with defined prefix) as a [`TreeNode`], splitting the rest of each key by `delimiter`.
+    /// e.g. `users/1/name` with prefix `users/` and `/` becomes `1` then `name`. Useful for rendering a hierarchy.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    fn list_tree(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        delimiter: char,
+    ) -> Result<TreeNode, Error>;
+    /// Delete every variable with defined prefix. Returns how many variables were deleted.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidPrefixError`] for empty prefix while it's required.
+    fn delete_prefix(&self, prefix: impl AsRef<str>) -> Result<usize, Error>;
+    /// Delete every variable (optionally with defined prefix) whose `(key, value)` makes `f` return `true`, e.g. expired markers.
+    /// Every value is fetched to be checked. Returns how many variables were deleted.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidPrefixError`] for empty prefix while it's required.
+    fn delete_where<F: Fn(&str, &str) -> bool>(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        f: F,
+    ) -> Result<usize, Error>;
+    /// Like [`Synchronous::list`] but retries up to `max_retries` times while the result is empty.
+    /// This is a heuristic for databases you know aren't empty, as the server could rarely give out an empty listing.
+    /// A genuinely empty result is still returned after the retries are used up.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    fn list_expect_nonempty(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        max_retries: usize,
+    ) -> Result<std::vec::Vec<String>, Error>;
+    /// Same as [`Synchronous::list`] but tells whether an empty result was explicitly indicated by the server or is an ambiguous empty body.
+    /// (See [`ListResult`]) Useful for callers that need to decide whether to trust emptiness.
+    /// Only a `204` counts as explicit, Replit's server never sends one so its empty listings are always ambiguous.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidPrefixError`] for empty prefix while it's required.
+    fn list_checked(&self, prefix: Option<impl AsRef<str>>) -> Result<ListResult, Error>;
+    /// Check if a variable exists. `key` MUST implement [`AsRef<str>`]. ([`str`] and [`String`] implemented this.).
+    /// Only asks for the headers (`HEAD`), so the value isn't downloaded and it works for values over [`Config::with_max_value_bytes`].
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key
+    fn exists(&self, key: impl AsRef<str>) -> Result<bool, Error>;
+    /// Check if the database is reachable and the URL is still authorized. Useful at startup.
+    fn ping(&self) -> PingStatus;
+    /// Get multiple variables at once. Result is in the same order as `keys`, with [`None`] for keys that aren't exists.
+    /// Repeated keys are only fetched once.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key
+    fn get_many(&self, keys: &[impl AsRef<str>]) -> Result<std::vec::Vec<Option<String>>, Error>;
+    /// Set multiple variables at once, packing as many pairs as possible into each request.
+    /// Requests are split so their body stays under [`Config::with_max_body_bytes`]. Every pair is checked before anything is sent.
+    /// If a request fails midway, pairs in the earlier requests are already written. Hooks and [`Config::with_circuit_breaker`] see every key of each request.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key, [`ErrorKind::InvalidValueError`] for rejected value, [`ErrorKind::ValueTooLargeError`] for value (or pair) over the limit, [`ErrorKind::ReservedValueError`] for value colliding with a reserved format
+    fn set_many(&self, pairs: &[(impl AsRef<str>, impl AsRef<str>)]) -> Result<(), Error>;
+    /// List variables (optionally with defined prefix) with their values, but only fetch values smaller than `max_bytes`.
+    /// Each key's size is checked with a HEAD request first, larger values are [`None`]. Useful for browsing without pulling megabytes.
+    /// Values are post-processed like [`Synchronous::get`] (See [`Config::with_trim_trailing_newline`]). Keys deleted after listing (404) are left out.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError (including any other unsuccessful status), [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidKeyError`] for over-long key.
+    fn list_with_small_values(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        max_bytes: usize,
+    ) -> Result<std::vec::Vec<(String, Option<String>)>, Error>;
+    /// Get a variable that isn't UTF-8, decoding raw bytes with the encoding named by `label` (e.g. `"latin1"`, see [Encoding Standard's labels](https://encoding.spec.whatwg.org/#names-and-labels)). (`encoding` feature)
+    /// Possible Exceptions are same as [`Synchronous::get`] plus [`ErrorKind::DecodeError`] for unknown label or undecodable bytes.
+    #[cfg(feature = "encoding")]
+    fn get_with_encoding(&self, key: impl AsRef<str>, label: &str) -> Result<String, Error>;
+    /// Same as [`Synchronous::get`] but also returns `Last-Modified` and `ETag` headers when the server sends them. Useful for change tracking.
+    /// Unparsable `Last-Modified` header is treated as missing.
+    /// Possible Exceptions are the same as [`Synchronous::get`]
+    fn get_with_metadata(&self, key: impl AsRef<str>) -> Result<ValueWithMetadata, Error>;
+    /// Get a variable from `primary` key, falling back to `fallback` key if the primary one isn't exists. Useful while renaming keys.
+    /// Returns [`None`] if neither of them exists.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key
+    fn get_with_fallback(
+        &self,
+        primary: impl AsRef<str>,
+        fallback: impl AsRef<str>,
+    ) -> Result<Option<String>, Error>;
+    /// Get a variable with stale-while-revalidate caching. (`cache` feature)
+    /// If the key was fetched within `max_stale`, the cached value is returned immediately and a refresh is started in the background,
+    /// otherwise the value is fetched (and cached) before returning.
+    ///
+    /// Concurrency model: at most one background refresh runs per key at a time, on a detached thread.
+    /// Refreshes never block callers, a failed refresh keeps the old value until it gets too stale. [`Synchronous::set`] and [`Synchronous::delete`] invalidate the cached key.
+    /// A fetch (or refresh) that was already running when its key got invalidated isn't cached, so it can't bring back a value from before the write.
+    /// Possible Exceptions are the same as [`Synchronous::get`] (only when the value isn't served from the cache).
+    #[cfg(feature = "cache")]
+    fn get_swr(
+        &self,
+        key: impl AsRef<str>,
+        max_stale: std::time::Duration,
+    ) -> Result<String, Error>;
+    /// Get a variable as a shared [`std::sync::Arc<str>`], for values read by many threads (e.g. configuration). (`cache` feature)
+    /// The first call fetches and caches the value, later calls hand out clones of the same allocation without any request.
+    /// The value is immutable, it's only refetched after [`Synchronous::set`] or [`Synchronous::delete`] on this database (or its clones) invalidates it.
+    /// Changes made by other clients aren't picked up, use [`Synchronous::get_swr`] for values that change.
+    /// Possible Exceptions are the same as [`Synchronous::get`] (only when the value isn't served from the cache).
+    #[cfg(feature = "cache")]
+    fn get_shared(&self, key: impl AsRef<str>) -> Result<std::sync::Arc<str>, Error>;
+    /// Copy every variable under `from_prefix` to the same key under `to_prefix` (e.g. `test/a` to `test2/a`). Sources are kept.
+    /// Returns how many variables were copied.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidKeyError`] for over-long key
+    fn clone_namespace(
+        &self,
+        from_prefix: impl AsRef<str>,
+        to_prefix: impl AsRef<str>,
+    ) -> Result<usize, Error>;
+    /// Copy every variable (optionally with defined prefix) from this database into `dest`, e.g. for migrating dev to prod.
+    /// Variables already existing in `dest` are skipped unless `overwrite` is `true`. Returns how many variables were copied.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    fn copy_all_to(
+        &self,
+        dest: &Database,
+        prefix: Option<impl AsRef<str>>,
+        overwrite: bool,
+    ) -> Result<usize, Error>;
+    /// Debugging helper for eventual consistency issues. Lists keys (optionally with defined prefix) then checks each of them exists (without downloading values),
+    /// reporting keys that were listed but missing on get. (Keys that can be fetched but aren't listed can't be detected, as there's nothing else to enumerate them from)
+    /// A key deleted between listing and getting is also reported, so avoid running it while something else is writing.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    fn consistency_check(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+    ) -> Result<ConsistencyReport, Error>;
+    /// Quick micro-benchmark. Performs `ops` operations (rounds of set, get then delete) on keys under [`RESERVED_PREFIX`] using `concurrency` threads
+    /// then deletes whatever is left behind. Useful for tuning concurrency limits.
+    /// Possible Exception is [`ErrorKind::HttpError`] for HttpError while cleaning up. (Failed operations are counted in [`BenchResult::errors`])
+    fn bench(&self, ops: usize, concurrency: usize) -> Result<BenchResult, Error>;
+    /// Measure write-to-read visibility delay (e.g. replication lag). Writes a key under [`RESERVED_PREFIX`] then polls [`Synchronous::get`]
+    /// until the written value shows up, returning the time since the write was acknowledged. The key is deleted afterwards.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::TimeoutError`] if the value didn't show up within `timeout`
+    fn measure_write_visibility(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<std::time::Duration, Error>;
+    /// Load `keys` into a map, let `f` mutate it then write back changed entries (removed entries are deleted). Keys that aren't exists are absent from the map.
+    /// Entries for keys outside of `keys` are ignored. If writing back fails midway, already written keys are rolled back. (Best-effort)
+    /// Returns what `f` returned.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key, [`ErrorKind::InvalidValueError`] for rejected value
+    fn with_snapshot<F, R>(&self, keys: &[&str], f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut std::collections::HashMap<String, String>) -> R;
+    /// Export variables (optionally with defined prefix like [`Synchronous::list`]) into a single JSON object mapping keys to values.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    #[cfg(feature = "serde")]
+    fn export_json(&self, prefix: Option<impl AsRef<str>>) -> Result<String, Error>;
+    /// Import variables from a JSON object made by [`Synchronous::export_json`]. Non-string values are stored as their JSON text.
+    /// Existing variables are skipped unless `overwrite` is `true`. Returns how many variables were written.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::JsonError`] for invalid JSON object.
+    #[cfg(feature = "serde")]
+    fn import_json(&self, json: &str, overwrite: bool) -> Result<usize, Error>;
+    /// Set a variable to `value` serialized as JSON. If `validator` is given, it runs before serializing and an [`Err`] from it
+    /// becomes [`ErrorKind::ValidationError`] without writing anything.
+    /// Possible Exceptions are same as [`Synchronous::set`] plus [`ErrorKind::ValidationError`] for rejected value, [`ErrorKind::JsonError`] for unserializable value.
+    #[cfg(feature = "serde")]
+    fn set_json<V>(
+        &self,
+        key: impl AsRef<str>,
+        value: &V,
+        validator: Option<JsonValidator<V>>,
+    ) -> Result<(), Error>
+    where
+        V: serde::Serialize;
+    /// Get a variable deserialized from JSON as `V`.
+    /// Possible Exceptions are same as [`Synchronous::get`] plus [`ErrorKind::JsonError`] for malformed value.
+    #[cfg(feature = "serde")]
+    fn get_json<V>(&self, key: impl AsRef<str>) -> Result<V, Error>
+    where
+        V: serde::de::DeserializeOwned;
+    /// Get a variable holding a JSON array, deserializing elements as `V` one by one while the body is being read.
+    /// This keeps memory bounded for huge arrays, unlike `get_json::<Vec<V>>`. Iteration stops after the first error.
+    /// Possible Exceptions (as items) are same as [`Synchronous::get`] plus [`ErrorKind::JsonError`] for malformed array.
+    #[cfg(feature = "serde")]
+    fn get_json_array_stream<V>(
+        &self,
+        key: impl AsRef<str>,
+    ) -> impl Iterator<Item = Result<V, Error>> + '_
+    where
+        V: serde::de::DeserializeOwned;
+    /// List variables (optionally with defined prefix) with their values deserialized from JSON as `V`.
+    /// Malformed values are skipped when `skip_malformed` is `true`, otherwise they're errors.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::JsonError`] for malformed value.
+    #[cfg(feature = "serde")]
+    fn list_with_values_json<V>(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        skip_malformed: bool,
+    ) -> Result<std::vec::Vec<(String, V)>, Error>
+    where
+        V: serde::de::DeserializeOwned;
 }
 
 /// Asynchronous support for Database struct. Use this trait by import it then use it right away!
 #[async_trait::async_trait]
 pub trait Asynchronous {
     /// Set a variable. `key` and `value` MUST implement [`AsRef<str>`]. ([`str`] and [`String`] implemented this.).
-    /// Possible Exception is [`ErrorKind::HttpError`] for HttpError
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key, [`ErrorKind::InvalidValueError`] for rejected value, [`ErrorKind::ValueTooLargeError`] for value over the limit, [`ErrorKind::ReservedValueError`] for value colliding with a reserved format
     async fn set<T>(&self, key: T, value: T) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send;
+    /// Same as [`Asynchronous::set`] but returns a [`WriteReceipt`] of the write. Useful for audit logs.
+    async fn set_with_receipt<T>(&self, key: T, value: T) -> Result<WriteReceipt, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Same as [`Asynchronous::set`] but skipped if a write with the same `idempotency_key` was already applied, for retrying without writing twice.
+    /// Applied idempotency keys are recorded in a sidecar key under `__replit_db__/idempotency/` which is never cleaned up by itself, so each one costs a key
+    /// (remove them with [`Asynchronous::delete_prefix`] once retries can't happen anymore). Returns whether the value was written. [`None`] always writes.
+    /// It's best-effort, two writes racing with the same idempotency key could both be applied.
+    /// Possible Exceptions are the same as [`Asynchronous::set`]
+    async fn set_idempotent<T>(
+        &self,
+        key: T,
+        value: T,
+        idempotency_key: Option<&str>,
+    ) -> Result<bool, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Same as [`Asynchronous::set`] but the value can be anything that implements [`std::fmt::Display`] (e.g. numbers), it's formatted before being stored.
+    /// Possible Exceptions are the same as [`Asynchronous::set`]
+    async fn set_display<T, V>(&self, key: T, value: V) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+        V: std::fmt::Display + Send;
+    /// Same as [`Asynchronous::set`] but the value is gzip compressed then base64 encoded before being stored. Useful for large, repetitive values. (`compression` feature)
+    /// Read it back with [`Config::with_auto_decompress`] enabled.
+    /// Possible Exceptions are the same as [`Asynchronous::set`] (size limit applies to the compressed value)
+    #[cfg(feature = "compression")]
+    async fn set_compressed<T>(&self, key: T, value: T) -> Result<(), Error>
     where
         T: AsRef<str> + Send;
     /// Get a variable you just set. `key` MUST implement [`AsRef<str>`]. ([`str`] and [`String`] implemented this.).
-    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::NoItemFoundError`] for no items were found in the database
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::NoItemFoundError`] for no items were found in the database, [`ErrorKind::InvalidKeyError`] for over-long key, [`ErrorKind::ValueTooLargeError`] for value over the limit
     async fn get<T>(&self, key: T) -> Result<String, Error>
     where
         T: AsRef<str> + Send;
+    /// Like [`Asynchronous::get`] but retries up to `max_retries` times (with increasing delay) while the value is empty.
+    /// This is a heuristic for keys you know never hold an empty value, as the server could rarely give out an empty body for them.
+    /// A genuinely empty value is still returned after the retries are used up, the bound is what keeps it from retrying forever.
+    /// Possible Exceptions are the same as [`Asynchronous::get`]
+    async fn get_nonempty_retry<T>(&self, key: T, max_retries: usize) -> Result<String, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Same as [`Asynchronous::get`] but parses the value with [`std::str::FromStr`]. (e.g. `db.get_parsed::<i64, _>("counter")`)
+    /// Possible Exceptions are the same as [`Asynchronous::get`] and [`ErrorKind::ParseError`] for unparsable value
+    async fn get_parsed<V, T>(&self, key: T) -> Result<V, Error>
+    where
+        V: std::str::FromStr,
+        V::Err: std::fmt::Display,
+        T: AsRef<str> + Send;
     /// Delete a variable you just set. MUST implement [`AsRef<str>`]. ([`str`] and [`String`] implemented this.).
-    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::NoItemFoundError`] for no items were found in the database
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::NoItemFoundError`] for no items were found in the database, [`ErrorKind::InvalidKeyError`] for over-long key
     async fn delete<T>(&self, key: T) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send;
+    /// Stronger variant of [`Asynchronous::delete`], after deleting it checks with [`Asynchronous::exists`] that the key is really gone.
+    /// Checks again up to `max_retries` times (with increasing delay) while the key still shows up.
+    /// Possible Exceptions are the same as [`Asynchronous::delete`] and [`ErrorKind::ConsistencyError`] for key that's still there after the retries
+    async fn delete_verified<T>(&self, key: T, max_retries: usize) -> Result<(), Error>
     where
         T: AsRef<str> + Send;
     /// List variables. Optionally finding variable that contains defined prefix by passing [`Some`] with anything that implements [`AsRef<str>`]. ([`str`] and [`String`] implemented this.) or [`NONE`].
@@ -138,8 +828,253 @@ pub trait Asynchronous {
     async fn list<T>(&self, prefix: Option<T>) -> Result<std::vec::Vec<String>, Error>
     where
         T: AsRef<str> + Send;
+    /// List variables whose name starts with `prefix`, ignoring case. (e.g. `user` matches `User1` and `USER2`)
+    /// The server's prefix matching is case-sensitive, so this lists EVERY key in the database and filters them locally.
+    /// It's a full scan, avoid it on large databases or in hot paths.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidPrefixError`] for empty prefix while it's required.
+    async fn list_prefix_ci<T>(&self, prefix: T) -> Result<std::vec::Vec<String>, Error>
+    where
+        T: AsRef<str> + Send;
+    /// List a page of up to `page_size` variables (optionally with defined prefix) in key order, starting after `cursor`.
+    /// Returns the cursor of the next page, or [`None`] on the last page. Keys written behind the cursor while scanning won't show up.
+    /// Replit doesn't paginate listings, so every page lists every matching key and cuts the page out locally.
+    /// Paging through N keys downloads about N²/`page_size` keys in total, use [`Asynchronous::list`] instead to read all of them.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidArgumentError`] for `page_size` of 0.
+    async fn list_page<T>(
+        &self,
+        prefix: Option<T>,
+        cursor: Option<&ListCursor>,
+        page_size: usize,
+    ) -> Result<(std::vec::Vec<String>, Option<ListCursor>), Error>
+    where
+        T: AsRef<str> + Send;
+    /// List variables (optionally with defined prefix) into a bounded [`futures::channel::mpsc`] channel, sending each key as soon as it's read from the response.
+    /// Waits while the channel is full (backpressure). Stops early without an error if the receiver got dropped.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    async fn list_to_sender<T>(
+        &self,
+        prefix: Option<T>,
+        tx: futures::channel::mpsc::Sender<String>,
+    ) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send;
+    /// List variables (optionally with defined prefix) into any [`futures::Sink`] (e.g. a file writer, a channel or a transform),
+    /// feeding each key as soon as it's read from the response. Waits while the sink isn't ready (backpressure).
+    /// The sink is closed after the last key.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::SinkError`] if the sink failed.
+    async fn list_into_sink<T, S>(&self, prefix: Option<T>, sink: S) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+        S: futures::Sink<String> + Send,
+        S::Error: std::fmt::Display;
+    /// List variables (optionally with defined prefix) as a [`TreeNode`], splitting the rest of each key by `delimiter`.
+    /// e.g. `users/1/name` with prefix `users/` and `/` becomes `1` then `name`. Useful for rendering a hierarchy.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    async fn list_tree<T>(&self, prefix: Option<T>, delimiter: char) -> Result<TreeNode, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Delete every variable with defined prefix. Returns how many variables were deleted.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidPrefixError`] for empty prefix while it's required.
+    async fn delete_prefix<T>(&self, prefix: T) -> Result<usize, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Delete every variable (optionally with defined prefix) whose `(key, value)` makes `f` return `true`, e.g. expired markers.
+    /// Every value is fetched to be checked, with a bounded number of requests in flight. Returns how many variables were deleted.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidPrefixError`] for empty prefix while it's required.
+    async fn delete_where<T, F>(&self, prefix: Option<T>, f: F) -> Result<usize, Error>
+    where
+        T: AsRef<str> + Send,
+        F: Fn(&str, &str) -> bool + Send + Sync;
+    /// Like [`Asynchronous::list`] but retries up to `max_retries` times while the result is empty.
+    /// This is a heuristic for databases you know aren't empty, as the server could rarely give out an empty listing.
+    /// A genuinely empty result is still returned after the retries are used up.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    async fn list_expect_nonempty<T>(
+        &self,
+        prefix: Option<T>,
+        max_retries: usize,
+    ) -> Result<std::vec::Vec<String>, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Same as [`Asynchronous::list`] but tells whether an empty result was explicitly indicated by the server or is an ambiguous empty body.
+    /// (See [`ListResult`]) Useful for callers that need to decide whether to trust emptiness.
+    /// Only a `204` counts as explicit, Replit's server never sends one so its empty listings are always ambiguous.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidPrefixError`] for empty prefix while it's required.
+    async fn list_checked<T>(&self, prefix: Option<T>) -> Result<ListResult, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Check if a variable exists. `key` MUST implement [`AsRef<str>`]. ([`str`] and [`String`] implemented this.).
+    /// Only asks for the headers (`HEAD`), so the value isn't downloaded and it works for values over [`Config::with_max_value_bytes`].
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key
+    async fn exists<T>(&self, key: T) -> Result<bool, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Check if the database is reachable and the URL is still authorized. Useful at startup.
+    async fn ping(&self) -> PingStatus;
+    /// Get multiple variables at once. Result is in the same order as `keys`, with [`None`] for keys that aren't exists.
+    /// Repeated keys are only fetched once.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key
+    async fn get_many<T>(&self, keys: &[T]) -> Result<std::vec::Vec<Option<String>>, Error>
+    where
+        T: AsRef<str> + Sync;
+    /// Set multiple variables at once, packing as many pairs as possible into each request.
+    /// Requests are split so their body stays under [`Config::with_max_body_bytes`]. Every pair is checked before anything is sent.
+    /// If a request fails midway, pairs in the earlier requests are already written. Hooks and [`Config::with_circuit_breaker`] see every key of each request.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key, [`ErrorKind::InvalidValueError`] for rejected value, [`ErrorKind::ValueTooLargeError`] for value (or pair) over the limit, [`ErrorKind::ReservedValueError`] for value colliding with a reserved format
+    async fn set_many<K, V>(&self, pairs: &[(K, V)]) -> Result<(), Error>
+    where
+        K: AsRef<str> + Sync,
+        V: AsRef<str> + Sync;
+    /// List variables (optionally with defined prefix) with their values, but only fetch values smaller than `max_bytes`.
+    /// Each key's size is checked with a HEAD request first, larger values are [`None`]. Useful for browsing without pulling megabytes.
+    /// Values are post-processed like [`Asynchronous::get`] (See [`Config::with_trim_trailing_newline`]). Keys deleted after listing (404) are left out.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError (including any other unsuccessful status), [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidKeyError`] for over-long key.
+    async fn list_with_small_values<T>(
+        &self,
+        prefix: Option<T>,
+        max_bytes: usize,
+    ) -> Result<std::vec::Vec<(String, Option<String>)>, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Get a variable that isn't UTF-8, decoding raw bytes with the encoding named by `label` (e.g. `"latin1"`, see [Encoding Standard's labels](https://encoding.spec.whatwg.org/#names-and-labels)). (`encoding` feature)
+    /// Possible Exceptions are same as [`Asynchronous::get`] plus [`ErrorKind::DecodeError`] for unknown label or undecodable bytes.
+    #[cfg(feature = "encoding")]
+    async fn get_with_encoding<T>(&self, key: T, label: &str) -> Result<String, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Same as [`Asynchronous::get`] but also returns `Last-Modified` and `ETag` headers when the server sends them. Useful for change tracking.
+    /// Unparsable `Last-Modified` header is treated as missing.
+    /// Possible Exceptions are the same as [`Asynchronous::get`]
+    async fn get_with_metadata<T>(&self, key: T) -> Result<ValueWithMetadata, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Get a variable from `primary` key, falling back to `fallback` key if the primary one isn't exists. Useful while renaming keys.
+    /// Returns [`None`] if neither of them exists.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key
+    async fn get_with_fallback<T>(&self, primary: T, fallback: T) -> Result<Option<String>, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Get a variable with stale-while-revalidate caching. (`cache` feature)
+    /// If the key was fetched within `max_stale`, the cached value is returned immediately and a refresh is started in the background,
+    /// otherwise the value is fetched (and cached) before returning.
+    ///
+    /// Concurrency model: at most one background refresh runs per key at a time, spawned on the current Tokio runtime.
+    /// Refreshes never block callers, a failed refresh keeps the old value until it gets too stale. [`Asynchronous::set`] and [`Asynchronous::delete`] invalidate the cached key.
+    /// A fetch (or refresh) that was already running when its key got invalidated isn't cached, so it can't bring back a value from before the write.
+    /// Possible Exceptions are the same as [`Asynchronous::get`] (only when the value isn't served from the cache).
+    #[cfg(feature = "cache")]
+    async fn get_swr<T>(&self, key: T, max_stale: std::time::Duration) -> Result<String, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Get a variable as a shared [`std::sync::Arc<str>`], for values read by many tasks (e.g. configuration). (`cache` feature)
+    /// The first call fetches and caches the value, later calls hand out clones of the same allocation without any request.
+    /// The value is immutable, it's only refetched after [`Asynchronous::set`] or [`Asynchronous::delete`] on this database (or its clones) invalidates it.
+    /// Changes made by other clients aren't picked up, use [`Asynchronous::get_swr`] for values that change.
+    /// Possible Exceptions are the same as [`Asynchronous::get`] (only when the value isn't served from the cache).
+    #[cfg(feature = "cache")]
+    async fn get_shared<T>(&self, key: T) -> Result<std::sync::Arc<str>, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Copy every variable under `from_prefix` to the same key under `to_prefix` (e.g. `test/a` to `test2/a`). Sources are kept.
+    /// Returns how many variables were copied.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::InvalidKeyError`] for over-long key
+    async fn clone_namespace<T>(&self, from_prefix: T, to_prefix: T) -> Result<usize, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Copy every variable (optionally with defined prefix) from this database into `dest`, e.g. for migrating dev to prod.
+    /// Variables already existing in `dest` are skipped unless `overwrite` is `true`. Returns how many variables were copied.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    async fn copy_all_to<T>(
+        &self,
+        dest: &Database,
+        prefix: Option<T>,
+        overwrite: bool,
+    ) -> Result<usize, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Debugging helper for eventual consistency issues. Lists keys (optionally with defined prefix) then checks each of them exists (without downloading values),
+    /// reporting keys that were listed but missing on get. (Keys that can be fetched but aren't listed can't be detected, as there's nothing else to enumerate them from)
+    /// A key deleted between listing and getting is also reported, so avoid running it while something else is writing.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    async fn consistency_check<T>(&self, prefix: Option<T>) -> Result<ConsistencyReport, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Quick micro-benchmark. Performs `ops` operations (rounds of set, get then delete) on keys under [`RESERVED_PREFIX`] with `concurrency` requests in flight
+    /// then deletes whatever is left behind. Useful for tuning concurrency limits.
+    /// Possible Exception is [`ErrorKind::HttpError`] for HttpError while cleaning up. (Failed operations are counted in [`BenchResult::errors`])
+    async fn bench(&self, ops: usize, concurrency: usize) -> Result<BenchResult, Error>;
+    /// Measure write-to-read visibility delay (e.g. replication lag). Writes a key under [`RESERVED_PREFIX`] then polls [`Asynchronous::get`]
+    /// until the written value shows up, returning the time since the write was acknowledged. The key is deleted afterwards.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::TimeoutError`] if the value didn't show up within `timeout`
+    async fn measure_write_visibility(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<std::time::Duration, Error>;
+    /// Load `keys` into a map, let `f` mutate it then write back changed entries (removed entries are deleted). Keys that aren't exists are absent from the map.
+    /// Entries for keys outside of `keys` are ignored. If writing back fails midway, already written keys are rolled back. (Best-effort)
+    /// Returns what `f` returned.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::InvalidKeyError`] for over-long key, [`ErrorKind::InvalidValueError`] for rejected value
+    async fn with_snapshot<F, R>(&self, keys: &[&str], f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut std::collections::HashMap<String, String>) -> R + Send,
+        R: Send;
+    /// Export variables (optionally with defined prefix like [`Asynchronous::list`]) into a single JSON object mapping keys to values.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error.
+    #[cfg(feature = "serde")]
+    async fn export_json<T>(&self, prefix: Option<T>) -> Result<String, Error>
+    where
+        T: AsRef<str> + Send;
+    /// Import variables from a JSON object made by [`Asynchronous::export_json`]. Non-string values are stored as their JSON text.
+    /// Existing variables are skipped unless `overwrite` is `true`. Returns how many variables were written.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::JsonError`] for invalid JSON object.
+    #[cfg(feature = "serde")]
+    async fn import_json(&self, json: &str, overwrite: bool) -> Result<usize, Error>;
+    /// Set a variable to `value` serialized as JSON. If `validator` is given, it runs before serializing and an [`Err`] from it
+    /// becomes [`ErrorKind::ValidationError`] without writing anything.
+    /// Possible Exceptions are same as [`Asynchronous::set`] plus [`ErrorKind::ValidationError`] for rejected value, [`ErrorKind::JsonError`] for unserializable value.
+    #[cfg(feature = "serde")]
+    async fn set_json<T, V>(
+        &self,
+        key: T,
+        value: &V,
+        validator: Option<JsonValidator<V>>,
+    ) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+        V: serde::Serialize + Sync;
+    /// Get a variable deserialized from JSON as `V`.
+    /// Possible Exceptions are same as [`Asynchronous::get`] plus [`ErrorKind::JsonError`] for malformed value.
+    #[cfg(feature = "serde")]
+    async fn get_json<T, V>(&self, key: T) -> Result<V, Error>
+    where
+        T: AsRef<str> + Send,
+        V: serde::de::DeserializeOwned;
+    /// Get a variable holding a JSON array as a [`futures::Stream`], deserializing elements as `V` one by one while the body is being read.
+    /// This keeps memory bounded for huge arrays, unlike `get_json::<Vec<V>>`. The stream ends after the first error.
+    /// Possible Exceptions (as items) are same as [`Asynchronous::get`] plus [`ErrorKind::JsonError`] for malformed array.
+    #[cfg(feature = "serde")]
+    fn get_json_array_stream<T, V>(
+        &self,
+        key: T,
+    ) -> impl futures::Stream<Item = Result<V, Error>> + Send + '_
+    where
+        T: AsRef<str> + Send,
+        V: serde::de::DeserializeOwned + Send;
+    /// List variables (optionally with defined prefix) with their values deserialized from JSON as `V`.
+    /// Malformed values are skipped when `skip_malformed` is `true`, otherwise they're errors.
+    /// Possible Exceptions are [`ErrorKind::HttpError`] for HttpError, [`ErrorKind::DecodeError`] Decoding string error, [`ErrorKind::JsonError`] for malformed value.
+    #[cfg(feature = "serde")]
+    async fn list_with_values_json<T, V>(
+        &self,
+        prefix: Option<T>,
+        skip_malformed: bool,
+    ) -> Result<std::vec::Vec<(String, V)>, Error>
+    where
+        T: AsRef<str> + Send,
+        V: serde::de::DeserializeOwned + Send;
 }
 
+#[allow(clippy::needless_return, clippy::unnecessary_unwrap)]
 impl Config {
     /// Creating new [`Config`] struct with default configuration. (This will get Replit's Database URL through enviroment variable `REPLIT_DB_URL`)
     /// With a possibility of [`std::env::VarError`] due to enviroment variable isn't exists.
@@ -149,243 +1084,825 @@ impl Config {
         if res.is_err() {
             return Err(res.err().unwrap());
         }
-        return Ok(Self { url: res.unwrap() });
+        return Ok(Self::new_custom_url(res.unwrap().as_str()));
+    }
+
+    /// Same as [`Config::new_custom_url`] but the URL is checked first, so a malformed one is caught here rather than on the first request.
+    /// Possible Exception is [`ErrorKind::InvalidUrlError`] for URL that isn't a valid `http`/`https` URL.
+    pub fn try_new_custom_url(url: &str) -> Result<Config, Error> {
+        let parsed = reqwest::Url::parse(url);
+        if parsed.is_err() {
+            return Err(Error {
+                kind: ErrorKind::InvalidUrlError,
+                message: format!("Database URL is invalid: {}", parsed.unwrap_err()),
+            });
+        }
+        let scheme = parsed.unwrap().scheme().to_string();
+        if scheme != "http" && scheme != "https" {
+            return Err(Error {
+                kind: ErrorKind::InvalidUrlError,
+                message: format!("Database URL has unsupported scheme {:?}.", scheme),
+            });
+        }
+        return Ok(Self::new_custom_url(url));
     }
 
     /// Creating a new [`Config`] struct with custom URL configuration.
+    /// The URL isn't checked, use [`Config::try_new_custom_url`] to catch a malformed one early.
     pub fn new_custom_url(url: &str) -> Config {
-        return Ok(Self {
+        return Self {
             url: url.to_owned(),
-        };)
+            max_key_length: REPLIT_MAX_KEY_LENGTH,
+            reject_control_chars_in_values: false,
+            max_value_bytes: None,
+            require_prefix: false,
+            max_concurrent_requests: None,
+            trim_trailing_newline: false,
+            sanitize_keys: None,
+            max_body_bytes: None,
+            circuit_breaker: None,
+            #[cfg(feature = "compression")]
+            auto_decompress: false,
+        };
     }
-}
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        return f.write_str(format!("{:#?}: {}", self.kind, self.message).as_str());
+    /// Set maximum key's length in bytes. Longer keys are rejected with [`ErrorKind::InvalidKeyError`] before sending the request.
+    /// Default is [`REPLIT_MAX_KEY_LENGTH`].
+    pub fn with_max_key_length(mut self, max_key_length: usize) -> Config {
+        self.max_key_length = max_key_length;
+        return self;
     }
-}
 
-impl std::error::Error for Error {} // Thanks nox!
+    /// Reject values containing control characters like `\n` (tab is allowed) with [`ErrorKind::InvalidValueError`] before sending the request.
+    /// Default is `false`.
+    pub fn with_reject_control_chars_in_values(mut self, reject: bool) -> Config {
+        self.reject_control_chars_in_values = reject;
+        return self;
+    }
 
-impl Database {
-    /// Creating new Database instance with [`Config`] struct.
-    /// You still need traits for this struct to work.
-    pub fn new(config: Config) -> Self {
-        return Self { config: config };
+    /// Set maximum value's size in bytes. `set` rejects larger values with [`ErrorKind::ValueTooLargeError`] before sending the request
+    /// and `get` stops reading as soon as the value goes over it, so an enormous value can't blow up the memory.
+    /// Default is [`None`]. (No limit, see [`REPLIT_MAX_VALUE_BYTES`] for Replit's limit)
+    /// Values that are too large to be stored in one key have to be chunked across several keys by yourself.
+    pub fn with_max_value_bytes(mut self, max_value_bytes: Option<usize>) -> Config {
+        self.max_value_bytes = max_value_bytes;
+        return self;
+    }
+
+    /// Require a non-empty prefix for `list` (and everything built on it like `delete_prefix`), so an accidental empty prefix
+    /// can't match the whole database. Empty or whitespace prefixes are rejected with [`ErrorKind::InvalidPrefixError`].
+    /// Default is `false`.
+    pub fn with_require_prefix(mut self, require_prefix: bool) -> Config {
+        self.require_prefix = require_prefix;
+        return self;
+    }
+
+    /// Limit how many requests a [`Database`] (and its clones) can have in flight at once, across every operation and both traits.
+    /// Requests over the limit wait for a slot before being sent. A slot is held until the response's body is read. `Some(0)` is treated as `Some(1)`.
+    /// Streaming methods (`list_to_sender`, `list_into_sink`, `get_json_array_stream`) hold theirs until they're done,
+    /// so requests made from their consumer meanwhile need a spare slot.
+    /// Default is [`None`]. (No limit)
+    pub fn with_max_concurrent_requests(
+        mut self,
+        max_concurrent_requests: Option<usize>,
+    ) -> Config {
+        self.max_concurrent_requests = max_concurrent_requests;
+        return self;
+    }
+
+    /// Strip a single trailing `\n` (or `\r\n`) from values returned by `get` (and everything built on it), for values written by tools that append one.
+    /// Default is `false`. (Values are returned as-is)
+    pub fn with_trim_trailing_newline(mut self, trim_trailing_newline: bool) -> Config {
+        self.trim_trailing_newline = trim_trailing_newline;
+        return self;
+    }
+
+    /// Rewrite keys with `sanitize_keys` before sending them (e.g. replacing newlines with underscores) rather than having them rejected.
+    /// Applies to every key based operation, so `get("a b")` reads what `set("a b", ..)` wrote. Prefixes are left as-is.
+    /// The sanitizer should give the same key when applied twice, as keys from `list` get sanitized again.
+    /// Use [`Database::stored_key`] (or `set_with_receipt`) to know the actual stored key.
+    /// Default is [`None`]. (Keys are sent as-is)
+    pub fn with_sanitize_keys(mut self, sanitize_keys: Option<fn(&str) -> String>) -> Config {
+        self.sanitize_keys = sanitize_keys;
+        return self;
+    }
+
+    /// Set maximum request body's size in bytes for `set_many`. Batches going over it are split into several requests, each under the limit.
+    /// Default is [`None`]. (Every pair is sent in one request)
+    pub fn with_max_body_bytes(mut self, max_body_bytes: Option<usize>) -> Config {
+        self.max_body_bytes = max_body_bytes;
+        return self;
+    }
+
+    /// Stop sending requests for a key after `failure_threshold` consecutive failures (the request couldn't be sent or the server answered with 5xx),
+    /// calls for it fail with [`ErrorKind::CircuitOpenError`] until `cooldown` has passed. Then one request is let through as a probe,
+    /// a success closes the circuit while a failure opens it again for another `cooldown`. Keys are tracked separately (prefixes for `list`),
+    /// clones of a [`Database`] share the same circuits. `failure_threshold` of `0` is treated as `1`.
+    /// At most 1024 failing keys are tracked, past that the ones that haven't failed for a `cooldown` (or else the one that failed longest ago) are forgotten.
+    /// `ping` isn't affected, it always sends its request.
+    /// Default is [`None`]. (Requests are always sent)
+    pub fn with_circuit_breaker(
+        mut self,
+        circuit_breaker: Option<(usize, std::time::Duration)>,
+    ) -> Config {
+        self.circuit_breaker = circuit_breaker;
+        return self;
+    }
+
+    /// Detect values written by `set_compressed` in `get` (and everything built on it) and decompress them, so callers don't need to know whether a value was compressed.
+    /// Values are detected by the (base64 encoded) gzip magic bytes, anything else is passed through as-is. (`compression` feature)
+    /// [`Config::with_max_value_bytes`] also applies to the decompressed value, decompression stops as soon as it goes over it.
+    /// Default is `false`.
+    #[cfg(feature = "compression")]
+    pub fn with_auto_decompress(mut self, auto_decompress: bool) -> Config {
+        self.auto_decompress = auto_decompress;
+        return self;
     }
 }
 
-impl Synchronous for Database {
-    fn set(&self, key: impl AsRef<str>, value: impl AsRef<str>) -> Result<(), Error> {
-        let client = reqwest::blocking::Client::new();
-        let payload = format!(
-            "{}={}",
-            urlencoding::encode(key.as_ref()),
-            urlencoding::encode(value.as_ref())
-        );
-        let response = client
-            .post(self.config.url.as_str().to_string())
-            .body(payload)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .send();
-        if response.is_err() {
-            return Err(Error {
-                kind: ErrorKind::HttpError,
-                message: response.unwrap_err().to_string(),
-            });
+#[allow(clippy::needless_return)]
+impl From<&str> for Config {
+    /// Same as [`Config::try_new_custom_url`], so `Database::new("https://...")` works.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the URL is invalid. (The message doesn't include the URL, as it contains the secret token)
+    fn from(url: &str) -> Config {
+        match Config::try_new_custom_url(url) {
+            Ok(config) => return config,
+            Err(e) => panic!("{}", e.message),
         }
-        return Ok(());
     }
+}
 
-    fn get(&self, key: impl AsRef<str>) -> Result<String, Error> {
-        let client = reqwest::blocking::Client::new();
-        let response = client
-            .get(
-                self.config.url.as_str().to_string()
-                    + format!("/{}", urlencoding::encode(key.as_ref())).as_str(),
-            )
-            .send();
-        // println!("{:#?}", response); debugging
-        if response.is_err() {
-            return Err(Error {
-                kind: ErrorKind::HttpError,
-                message: response.unwrap_err().to_string(),
-            });
-        }
-        let response = response.unwrap();
-        if !response.status().is_success() {
-            return Err(Error {
-                kind: ErrorKind::NoItemFoundError,
-                message: "No items were found on the database.".to_string(),
-            });
+#[allow(clippy::needless_return)]
+impl From<String> for Config {
+    /// Same as [`Config::try_new_custom_url`], so `Database::new(url)` works.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the URL is invalid. (The message doesn't include the URL, as it contains the secret token)
+    fn from(url: String) -> Config {
+        return Config::from(url.as_str());
+    }
+}
+
+#[allow(clippy::needless_return)]
+impl TreeNode {
+    /// Assemble a tree from keys listed under `prefix`.
+    fn from_keys(prefix: &str, keys: std::vec::Vec<String>, delimiter: char) -> Self {
+        let mut root = TreeNode::default();
+        for key in keys {
+            let mut node = &mut root;
+            let rest = key.strip_prefix(prefix).unwrap_or(&key);
+            if !rest.is_empty() {
+                for segment in rest.split(delimiter) {
+                    node = node.children.entry(segment.to_string()).or_default();
+                }
+            }
+            node.key = Some(key);
         }
-        let content = response.text().unwrap();
-        return Ok(content);
+        return root;
     }
+}
 
-    fn delete(&self, key: impl AsRef<str>) -> Result<(), Error> {
-        let client = reqwest::blocking::Client::new();
-        let response = client
-            .delete(
-                self.config.url.as_str().to_string()
-                    + format!("/{}", urlencoding::encode(key.as_ref())).as_str(),
-            )
-            .send();
+#[allow(clippy::needless_return)]
+impl PingStatus {
+    /// Whether the database is reachable and authorized. Useful as a health check's result.
+    pub fn is_healthy(&self) -> bool {
+        return *self == PingStatus::Reachable;
+    }
+}
 
-        if response.is_err() {
-            return Err(Error {
-                kind: ErrorKind::HttpError,
-                message: response.unwrap_err().to_string(),
-            });
+#[allow(clippy::needless_return)]
+impl ConsistencyReport {
+    /// Whether every listed key could be fetched.
+    pub fn is_consistent(&self) -> bool {
+        return self.missing_on_get.is_empty();
+    }
+}
+
+#[allow(clippy::needless_return)]
+impl ListResult {
+    /// Whether the listing is empty without the server saying so, so the emptiness may not be trusted (e.g. retry or check again later).
+    pub fn is_ambiguous_empty(&self) -> bool {
+        return self.keys.is_empty() && !self.explicit_empty;
+    }
+}
+
+#[allow(clippy::needless_return)]
+impl ListCursor {
+    /// Opaque token of this cursor, safe to store anywhere that takes a string.
+    pub fn to_token(&self) -> String {
+        return format!("v1:{}", urlencoding::encode(&self.last_key));
+    }
+
+    /// Load a cursor from a token made by [`ListCursor::to_token`].
+    /// Possible Exception is [`ErrorKind::DecodeError`] for invalid token.
+    pub fn from_token(token: &str) -> Result<ListCursor, Error> {
+        let decoded = token.strip_prefix("v1:").map(urlencoding::decode);
+        match decoded {
+            Some(Ok(last_key)) => {
+                return Ok(ListCursor {
+                    last_key: last_key.into_owned(),
+                })
+            }
+            _ => {
+                return Err(Error {
+                    kind: ErrorKind::DecodeError,
+                    message: format!("Invalid list cursor token: {}", token),
+                })
+            }
         }
-        if !response.unwrap().status().is_success() {
+    }
+
+    /// Reject a `page_size` of 0, as no page could ever move the cursor forward.
+    fn check_page_size(page_size: usize) -> Result<(), Error> {
+        if page_size == 0 {
             return Err(Error {
-                kind: ErrorKind::NoItemFoundError,
-                message: "No item with that name were found.".to_string(),
+                kind: ErrorKind::InvalidArgumentError,
+                message: "Page size must be at least 1.".to_string(),
             });
         }
         return Ok(());
     }
-    fn list(&self, prefix: Option<impl AsRef<str>>) -> Result<Vec<String>, Error> {
-        let prefix2 = match &prefix {
-            Some(p) => p.as_ref(),
-            None => "",
+
+    /// Take the page of `keys` after `cursor`, along with the cursor of the next page (if there's more).
+    fn page(
+        mut keys: std::vec::Vec<String>,
+        cursor: Option<&ListCursor>,
+        page_size: usize,
+    ) -> (std::vec::Vec<String>, Option<ListCursor>) {
+        keys.sort();
+        let start = match cursor {
+            Some(cursor) => keys.partition_point(|key| key <= &cursor.last_key),
+            None => 0,
         };
-        let client = reqwest::blocking::Client::new();
-        let response = client
-            .get(
-                self.config.url.as_str().to_string()
-                    + format!("?prefix={}", urlencoding::encode(prefix2)).as_str(),
-            )
-            .send();
-        if response.is_err() {
-            return Err(Error {
-                kind: ErrorKind::HttpError,
-                message: response.unwrap_err().to_string(),
-            });
-        }
-        let content = response.unwrap().text();
-        if content.is_err() {
-            return Err(Error {
-                kind: ErrorKind::DecodeError,
-                message: content.unwrap_err().to_string(),
-            });
-        }
-        let mut variables: std::vec::Vec<String> = std::vec::Vec::new();
-        for v in content.unwrap().lines() {
-            variables.push(v.to_string());
+        let mut page: std::vec::Vec<String> = keys.drain(start..).collect();
+        if page.len() <= page_size {
+            return (page, None);
         }
-        return Ok(variables);
+        page.truncate(page_size);
+        let next = ListCursor {
+            last_key: page.last().unwrap().clone(),
+        };
+        return (page, Some(next));
     }
 }
 
-#[async_trait::async_trait]
-impl Asynchronous for Database {
-    async fn set<T>(&self, key: T, value: T) -> Result<(), Error>
+#[allow(clippy::needless_return, clippy::redundant_field_names)]
+impl BenchResult {
+    fn new(ops: usize, errors: usize, elapsed: std::time::Duration) -> Self {
+        let seconds = elapsed.as_secs_f64();
+        return Self {
+            ops: ops,
+            errors: errors,
+            elapsed: elapsed,
+            ops_per_sec: if seconds > 0.0 {
+                ops as f64 / seconds
+            } else {
+                0.0
+            },
+            error_rate: if ops > 0 {
+                errors as f64 / ops as f64
+            } else {
+                0.0
+            },
+        };
+    }
+}
+
+#[allow(clippy::needless_return)]
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return f.write_str(format!("{:#?}: {}", self.kind, self.message).as_str());
+    }
+}
+
+impl std::error::Error for Error {} // Thanks nox!
+
+#[allow(
+    clippy::needless_return,
+    clippy::unnecessary_unwrap,
+    clippy::redundant_field_names
+)]
+impl Database {
+    /// Creating new Database instance with [`Config`] struct, or a database URL directly (`&str` or [`String`], see [`Config::try_new_custom_url`]).
+    /// You still need traits for this struct to work.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a URL is passed directly and it's invalid. Use [`Config::try_new_custom_url`] to handle it as an [`Error`] instead.
+    pub fn new(config: impl Into<Config>) -> Self {
+        let config: Config = config.into();
+        let limiter = config
+            .max_concurrent_requests
+            .map(|max| std::sync::Arc::new(Limiter::new(max.max(1))));
+        return Self {
+            config: std::sync::Arc::new(config),
+            hooks: std::vec::Vec::new(),
+            limiter: limiter,
+            circuits: std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
+            #[cfg(feature = "cache")]
+            cache: std::sync::Arc::new(std::sync::Mutex::new(Cache::default())),
+        };
+    }
+
+    /// Register closures called around every request this database sends.
+    /// `before` receives the [`Operation`] and the key (or the prefix for [`Operation::List`]),
+    /// `after` also receives the HTTP status code (`None` if the request couldn't be sent).
+    /// Hooks are called per HTTP request, not per method: a method sending several requests (e.g. `get_many` or retries) calls them for each one.
+    /// A batched write (`set_many`) calls them for each of its keys.
+    /// A request refused by [`Config::with_circuit_breaker`] isn't sent but still calls them, with `None` as the status.
+    /// Hooks never see the database URL as it contains the secret token.
+    /// Hooks are called in the order they're registered. Clones made before registering won't have it.
+    pub fn with_operation_hook<B, A>(mut self, before: B, after: A) -> Self
     where
-        T: AsRef<str> + Send,
+        B: Fn(Operation, &str) + Send + Sync + 'static,
+        A: Fn(Operation, &str, Option<u16>) + Send + Sync + 'static,
     {
-        let client = reqwest::Client::new();
-        let payload = format!(
-            "{}={}",
-            urlencoding::encode(key.as_ref()),
-            urlencoding::encode(value.as_ref())
-        );
-        let response = client
-            .post(self.config.url.as_str().to_string())
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(payload)
-            .send()
-            .await;
+        self.hooks.push(std::sync::Arc::new(OperationHook {
+            before: std::boxed::Box::new(before),
+            after: std::boxed::Box::new(after),
+        }));
+        return self;
+    }
+
+    /// The key that's actually stored for `key`, after [`Config::with_sanitize_keys`]. (Same as `key` if there's no sanitizer)
+    pub fn stored_key(&self, key: &str) -> String {
+        match self.config.sanitize_keys {
+            Some(sanitize) => return sanitize(key),
+            None => return key.to_string(),
+        }
+    }
+
+    /// Get a [`HealthService`] pinging this database. (`tower` feature)
+    #[cfg(feature = "tower")]
+    pub fn health_service(&self) -> HealthService {
+        return HealthService { db: self.clone() };
+    }
+
+    /// Get a [`BlockingAdapter`] running this database's [`Synchronous`] methods on Tokio's blocking thread pool. (`blocking-adapter` feature)
+    #[cfg(feature = "blocking-adapter")]
+    pub fn blocking_adapter(&self) -> BlockingAdapter {
+        return BlockingAdapter { db: self.clone() };
+    }
+
+    /// Fail with [`ErrorKind::CircuitOpenError`] if `key`'s circuit is open. Once the cooldown has passed,
+    /// the circuit is re-opened from now so only this request goes through as the probe.
+    fn circuit_check(&self, key: &str) -> Result<(), Error> {
+        let cooldown = match self.config.circuit_breaker {
+            Some((_, cooldown)) => cooldown,
+            None => return Ok(()),
+        };
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = match circuits.get_mut(key) {
+            Some(circuit) => circuit,
+            None => return Ok(()),
+        };
+        if let Some(opened_at) = circuit.opened_at {
+            let elapsed = opened_at.elapsed();
+            if elapsed < cooldown {
+                return Err(Error {
+                    kind: ErrorKind::CircuitOpenError,
+                    message: format!(
+                        "Circuit for key {:?} is open after {} consecutive failures, retry in {:?}",
+                        key,
+                        circuit.failures,
+                        cooldown - elapsed
+                    ),
+                });
+            }
+            circuit.opened_at = Some(std::time::Instant::now());
+        }
+        return Ok(());
+    }
+
+    /// [`Database::circuit_check`] every key of a request about to be sent, it's refused if any of their circuits is open.
+    /// A refused request still calls the hooks (for every key), as one that couldn't be sent.
+    fn circuit_admit(&self, operation: Operation, keys: &[&str]) -> Result<(), Error> {
+        let res = keys.iter().try_for_each(|key| self.circuit_check(key));
+        if res.is_err() {
+            for key in keys {
+                for hook in &self.hooks {
+                    (hook.before)(operation, key);
+                }
+            }
+            for key in keys {
+                for hook in &self.hooks {
+                    (hook.after)(operation, key, None);
+                }
+            }
+        }
+        return res;
+    }
+
+    /// Record the outcome of a request for `key`'s circuit. `status` is `None` if the request couldn't be sent.
+    fn circuit_record(&self, key: &str, status: Option<u16>) {
+        let (threshold, cooldown) = match self.config.circuit_breaker {
+            Some((threshold, cooldown)) => (threshold.max(1), cooldown),
+            None => return,
+        };
+        let mut circuits = self.circuits.lock().unwrap();
+        if status.is_some_and(|status| status < 500) {
+            circuits.remove(key);
+            return;
+        }
+        if circuits.len() >= MAX_CIRCUITS && !circuits.contains_key(key) {
+            // forget circuits that haven't failed for a cooldown first, then the one that failed longest ago
+            circuits.retain(|_, circuit| circuit.last_failure.elapsed() < cooldown);
+            if circuits.len() >= MAX_CIRCUITS {
+                let oldest = circuits
+                    .iter()
+                    .min_by_key(|(_, circuit)| circuit.last_failure)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    circuits.remove(&oldest);
+                }
+            }
+        }
+        let circuit = circuits.entry(key.to_string()).or_insert(Circuit {
+            failures: 0,
+            opened_at: None,
+            last_failure: std::time::Instant::now(),
+        });
+        circuit.failures += 1;
+        circuit.last_failure = std::time::Instant::now();
+        if circuit.failures >= threshold {
+            circuit.opened_at = Some(std::time::Instant::now());
+        }
+    }
+
+    /// Send a request, calling the registered hooks around it.
+    /// The returned permit has to be kept until the response's body is read.
+    fn send_blocking(
+        &self,
+        operation: Operation,
+        key: &str,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> Result<(reqwest::blocking::Response, RequestPermit), Error> {
+        return self.dispatch_blocking(operation, &[key], true, request);
+    }
+
+    /// [`Database::send_blocking`] for a request about several keys at once (a batched write),
+    /// every key's circuit is checked and recorded and the hooks are called for each of them. Without `circuit`, the circuit breaker is bypassed.
+    fn dispatch_blocking(
+        &self,
+        operation: Operation,
+        keys: &[&str],
+        circuit: bool,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> Result<(reqwest::blocking::Response, RequestPermit), Error> {
+        if circuit {
+            self.circuit_admit(operation, keys)?;
+        }
+        let permit = self
+            .limiter
+            .as_ref()
+            .map(|limiter| limiter.acquire_blocking());
+        for key in keys {
+            for hook in &self.hooks {
+                (hook.before)(operation, key);
+            }
+        }
+        let response = request.send();
+        let status = response.as_ref().ok().map(|r| r.status().as_u16());
+        if circuit {
+            for key in keys {
+                self.circuit_record(key, status);
+            }
+        }
+        for key in keys {
+            for hook in &self.hooks {
+                (hook.after)(operation, key, status);
+            }
+        }
         if response.is_err() {
             return Err(Error {
                 kind: ErrorKind::HttpError,
                 message: response.unwrap_err().to_string(),
             });
         }
-        return Ok(());
+        return Ok((response.unwrap(), permit));
     }
 
-    async fn get<T>(&self, key: T) -> Result<String, Error>
-    where
-        T: AsRef<str> + Send,
-    {
-        let client = reqwest::Client::new();
-        let response = client
-            .get(
-                self.config.url.as_str().to_string()
-                    + format!("/{}", urlencoding::encode(key.as_ref())).as_str(),
-            )
-            .send()
-            .await;
+    /// Send a request, calling the registered hooks around it.
+    /// The returned permit has to be kept until the response's body is read.
+    async fn send(
+        &self,
+        operation: Operation,
+        key: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<(reqwest::Response, RequestPermit), Error> {
+        return self.dispatch(operation, &[key], true, request).await;
+    }
+
+    /// [`Database::send`] for a request about several keys at once (a batched write),
+    /// every key's circuit is checked and recorded and the hooks are called for each of them. Without `circuit`, the circuit breaker is bypassed.
+    async fn dispatch(
+        &self,
+        operation: Operation,
+        keys: &[&str],
+        circuit: bool,
+        request: reqwest::RequestBuilder,
+    ) -> Result<(reqwest::Response, RequestPermit), Error> {
+        if circuit {
+            self.circuit_admit(operation, keys)?;
+        }
+        let permit = match &self.limiter {
+            Some(limiter) => Some(limiter.acquire().await),
+            None => None,
+        };
+        for key in keys {
+            for hook in &self.hooks {
+                (hook.before)(operation, key);
+            }
+        }
+        let response = request.send().await;
+        let status = response.as_ref().ok().map(|r| r.status().as_u16());
+        if circuit {
+            for key in keys {
+                self.circuit_record(key, status);
+            }
+        }
+        for key in keys {
+            for hook in &self.hooks {
+                (hook.after)(operation, key, status);
+            }
+        }
         if response.is_err() {
             return Err(Error {
                 kind: ErrorKind::HttpError,
                 message: response.unwrap_err().to_string(),
             });
         }
-        let response = response.unwrap();
-        if !response.status().is_success() {
+        return Ok((response.unwrap(), permit));
+    }
+
+    /// Look up the cache for `get_swr`. Returns the cached value if it's within `max_stale`
+    /// and whether the caller should start a background refresh (only one refresh per key at a time).
+    #[cfg(feature = "cache")]
+    fn cache_lookup(&self, key: &str, max_stale: std::time::Duration) -> Option<(String, bool)> {
+        let mut cache = self.cache.lock().unwrap();
+        let entry = cache.entries.get_mut(key)?;
+        if entry.fetched_at.elapsed() > max_stale {
+            return None;
+        }
+        let start_refresh = !entry.refreshing;
+        entry.refreshing = true;
+        return Some((entry.value.to_string(), start_refresh));
+    }
+
+    /// Start fetching `key` to be cached. Returns the generation to pass to [`Database::cache_fetch_end`] once the fetch is done.
+    #[cfg(feature = "cache")]
+    fn cache_fetch_start(&self, key: &str) -> u64 {
+        let mut cache = self.cache.lock().unwrap();
+        let fetch = cache.fetching.entry(key.to_string()).or_insert(CacheFetch {
+            running: 0,
+            invalidations: 0,
+        });
+        fetch.running += 1;
+        return fetch.invalidations;
+    }
+
+    /// Finish a fetch started with [`Database::cache_fetch_start`]. The fetched value is cached (and returned) unless the key got invalidated
+    /// since `generation`, as the fetch may have raced with a write. A key that's gone is removed, other errors keep serving the old value.
+    #[cfg(feature = "cache")]
+    fn cache_fetch_end(
+        &self,
+        key: &str,
+        generation: u64,
+        res: &Result<String, Error>,
+    ) -> Option<std::sync::Arc<str>> {
+        let mut cache = self.cache.lock().unwrap();
+        let invalidated = match cache.fetching.get_mut(key) {
+            Some(fetch) => {
+                fetch.running -= 1;
+                let invalidated = fetch.invalidations != generation;
+                if fetch.running == 0 {
+                    cache.fetching.remove(key);
+                }
+                invalidated
+            }
+            None => true,
+        };
+        if invalidated {
+            return None;
+        }
+        match res {
+            Ok(value) => {
+                let value: std::sync::Arc<str> = std::sync::Arc::from(value.as_str());
+                cache.entries.insert(
+                    key.to_string(),
+                    CacheEntry {
+                        value: value.clone(),
+                        fetched_at: std::time::Instant::now(),
+                        refreshing: false,
+                    },
+                );
+                return Some(value);
+            }
+            Err(e) => match e.kind {
+                ErrorKind::NoItemFoundError => {
+                    cache.entries.remove(key);
+                }
+                _ => {
+                    if let Some(entry) = cache.entries.get_mut(key) {
+                        entry.refreshing = false; // keep serving it, try again next time
+                    }
+                }
+            },
+        }
+        return None;
+    }
+
+    /// Cached value of a key, regardless of how old it is.
+    #[cfg(feature = "cache")]
+    fn cache_shared(&self, key: &str) -> Option<std::sync::Arc<str>> {
+        return self
+            .cache
+            .lock()
+            .unwrap()
+            .entries
+            .get(key)
+            .map(|entry| entry.value.clone());
+    }
+
+    /// Invalidate a cached key. (after it's changed or deleted)
+    #[cfg(feature = "cache")]
+    fn cache_remove(&self, key: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.entries.remove(key);
+        if let Some(fetch) = cache.fetching.get_mut(key) {
+            fetch.invalidations += 1;
+        }
+    }
+
+    /// Check the key against [`Config`]'s limits before sending anything.
+    fn validate_key(&self, key: &str) -> Result<(), Error> {
+        if key.len() > self.config.max_key_length {
             return Err(Error {
-                kind: ErrorKind::NoItemFoundError,
-                message: "No items were found on the database.".to_string(),
+                kind: ErrorKind::InvalidKeyError,
+                message: format!(
+                    "Key is {} bytes long which exceeds the limit of {} bytes.",
+                    key.len(),
+                    self.config.max_key_length
+                ),
             });
         }
-        let content = response.text().await.unwrap();
-        return Ok(content);
+        return Ok(());
     }
 
-    async fn delete<T>(&self, key: T) -> Result<(), Error>
-    where
-        T: AsRef<str> + Send,
-    {
-        let client = reqwest::Client::new();
-        let response = client
-            .delete(
-                self.config.url.as_str().to_string()
-                    + format!("/{}", urlencoding::encode(key.as_ref())).as_str(),
-            )
-            .send()
-            .await;
+    /// Check the prefix against [`Config::with_require_prefix`] before sending anything.
+    fn validate_prefix(&self, prefix: &str) -> Result<(), Error> {
+        if self.config.require_prefix && prefix.trim().is_empty() {
+            return Err(Error {
+                kind: ErrorKind::InvalidPrefixError,
+                message: "Prefix is required but an empty prefix was given.".to_string(),
+            });
+        }
+        return Ok(());
+    }
 
-        if response.is_err() {
+    /// Post-process a fetched value according to [`Config`]. (Decompressing it, stripping a trailing newline)
+    /// Decompressed values over [`Config::with_max_value_bytes`] are rejected with [`ErrorKind::ValueTooLargeError`].
+    fn finish_value(&self, mut value: String) -> Result<String, Error> {
+        #[cfg(feature = "compression")]
+        if self.config.auto_decompress {
+            if let Some(decompressed) = decompress_value(&value, self.config.max_value_bytes)? {
+                value = decompressed;
+            }
+        }
+        if self.config.trim_trailing_newline && value.ends_with('\n') {
+            value.pop();
+            if value.ends_with('\r') {
+                value.pop();
+            }
+        }
+        return Ok(value);
+    }
+
+    /// Check the value against [`Config`]'s rules before sending anything.
+    fn validate_value(&self, value: &str) -> Result<(), Error> {
+        if let Some(max_value_bytes) = self.config.max_value_bytes {
+            if value.len() > max_value_bytes {
+                return Err(value_too_large(max_value_bytes));
+            }
+        }
+        if self.config.reject_control_chars_in_values
+            && value.chars().any(|c| c.is_control() && c != '\t')
+        {
             return Err(Error {
-                kind: ErrorKind::HttpError,
-                message: response.unwrap_err().to_string(),
+                kind: ErrorKind::InvalidValueError,
+                message: "Value contains control characters.".to_string(),
             });
         }
-        if !response.unwrap().status().is_success() {
+        return Ok(());
+    }
+
+    /// Write a value without [`reject_reserved_value`], for `set`, `set_compressed` and copies of values read back as-is.
+    fn write_value_blocking(&self, key: &str, value: &str) -> Result<(), Error> {
+        let key = self.stored_key(key);
+        self.validate_key(&key)?;
+        self.validate_value(value)?;
+        let client = reqwest::blocking::Client::new();
+        let payload = format!(
+            "{}={}",
+            urlencoding::encode(&key),
+            urlencoding::encode(value)
+        );
+        let request = client
+            .post(self.config.url.as_str().to_string())
+            .body(payload)
+            .header("Content-Type", "application/x-www-form-urlencoded");
+        self.send_blocking(Operation::Set, &key, request)?;
+        #[cfg(feature = "cache")]
+        self.cache_remove(&key);
+        return Ok(());
+    }
+
+    /// Write a value without [`reject_reserved_value`], for `set`, `set_compressed` and copies of values read back as-is.
+    async fn write_value(&self, key: &str, value: &str) -> Result<(), Error> {
+        let key = self.stored_key(key);
+        self.validate_key(&key)?;
+        self.validate_value(value)?;
+        let client = reqwest::Client::new();
+        let payload = format!(
+            "{}={}",
+            urlencoding::encode(&key),
+            urlencoding::encode(value)
+        );
+        let request = client
+            .post(self.config.url.as_str().to_string())
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(payload);
+        self.send(Operation::Set, &key, request).await?;
+        #[cfg(feature = "cache")]
+        self.cache_remove(&key);
+        return Ok(());
+    }
+
+    /// Send a GET request for a key, returning the response with its body unread.
+    fn open_value_blocking(
+        &self,
+        key: &str,
+    ) -> Result<(reqwest::blocking::Response, RequestPermit), Error> {
+        let key = &self.stored_key(key);
+        self.validate_key(key)?;
+        let client = reqwest::blocking::Client::new();
+        let (response, permit) =
+            self.send_blocking(Operation::Get, key, client.get(self.key_url(key)))?;
+        if !response.status().is_success() {
             return Err(Error {
                 kind: ErrorKind::NoItemFoundError,
-                message: "No item with that name were found.".to_string(),
+                message: "No items were found on the database.".to_string(),
             });
         }
-        return Ok(());
+        return Ok((response, permit));
     }
-    async fn list<T>(&self, prefix: Option<T>) -> Result<Vec<String>, Error>
-    where
-        T: AsRef<str> + Send,
-    {
-        let prefix2 = match &prefix {
-            Some(p) => p.as_ref(),
-            None => "",
-        };
+
+    /// Send a GET request for a key, returning the response with its body unread.
+    async fn open_value(&self, key: &str) -> Result<(reqwest::Response, RequestPermit), Error> {
+        let key = &self.stored_key(key);
+        self.validate_key(key)?;
         let client = reqwest::Client::new();
-        let response = client
-            .get(
-                self.config.url.as_str().to_string()
-                    + format!("?prefix={}", urlencoding::encode(prefix2)).as_str(),
-            )
-            .send()
-            .await;
-        if response.is_err() {
+        let (response, permit) = self
+            .send(Operation::Get, key, client.get(self.key_url(key)))
+            .await?;
+        if !response.status().is_success() {
             return Err(Error {
-                kind: ErrorKind::HttpError,
-                message: response.unwrap_err().to_string(),
+                kind: ErrorKind::NoItemFoundError,
+                message: "No items were found on the database.".to_string(),
+            });
+        }
+        return Ok((response, permit));
+    }
+
+    /// List keys with a prefix, without checking [`Config::with_require_prefix`].
+    fn list_keys_blocking(&self, prefix: &str) -> Result<std::vec::Vec<String>, Error> {
+        let client = reqwest::blocking::Client::new();
+        let request = client.get(self.list_url(prefix));
+        let (response, _permit) = self.send_blocking(Operation::List, prefix, request)?;
+        let content = response.text();
+        if content.is_err() {
+            return Err(Error {
+                kind: ErrorKind::DecodeError,
+                message: content.unwrap_err().to_string(),
             });
         }
-        let content = response.unwrap().text().await;
+        let mut variables: std::vec::Vec<String> = std::vec::Vec::new();
+        for v in content.unwrap().lines() {
+            variables.push(v.to_string());
+        }
+        return Ok(variables);
+    }
+
+    /// List keys with a prefix, without checking [`Config::with_require_prefix`].
+    async fn list_keys(&self, prefix: &str) -> Result<std::vec::Vec<String>, Error> {
+        let client = reqwest::Client::new();
+        let request = client.get(self.list_url(prefix));
+        let (response, _permit) = self.send(Operation::List, prefix, request).await?;
+        let content = response.text().await;
         if content.is_err() {
             return Err(Error {
                 kind: ErrorKind::DecodeError,
@@ -398,4 +1915,4043 @@ impl Asynchronous for Database {
         }
         return Ok(variables);
     }
+
+    /// Encode pairs for `set_many` into request bodies under [`Config::with_max_body_bytes`], along with the (stored) keys of each body.
+    fn set_many_bodies<K: AsRef<str>, V: AsRef<str>>(
+        &self,
+        pairs: &[(K, V)],
+    ) -> Result<std::vec::Vec<(std::vec::Vec<String>, String)>, Error> {
+        let limit = self.config.max_body_bytes.unwrap_or(usize::MAX);
+        let mut bodies: std::vec::Vec<(std::vec::Vec<String>, String)> = std::vec::Vec::new();
+        for (key, value) in pairs {
+            let key = self.stored_key(key.as_ref());
+            self.validate_key(&key)?;
+            self.validate_value(value.as_ref())?;
+            reject_reserved_value(value.as_ref(), &self.config)?;
+            let pair = format!(
+                "{}={}",
+                urlencoding::encode(&key),
+                urlencoding::encode(value.as_ref())
+            );
+            if pair.len() > limit {
+                return Err(Error {
+                    kind: ErrorKind::ValueTooLargeError,
+                    message: format!(
+                        "Pair of {} is {} bytes encoded which exceeds the body limit of {} bytes.",
+                        key,
+                        pair.len(),
+                        limit
+                    ),
+                });
+            }
+            match bodies.last_mut() {
+                Some((keys, body)) if body.len() + 1 + pair.len() <= limit => {
+                    body.push('&');
+                    body.push_str(&pair);
+                    keys.push(key);
+                }
+                _ => bodies.push((vec![key], pair)),
+            }
+        }
+        return Ok(bodies);
+    }
+
+    /// URL listing keys with a prefix.
+    fn list_url(&self, prefix: &str) -> String {
+        return self.config.url.as_str().to_string()
+            + format!("?prefix={}", urlencoding::encode(prefix)).as_str();
+    }
+
+    /// URL of a key.
+    fn key_url(&self, key: &str) -> String {
+        return self.config.url.as_str().to_string()
+            + format!("/{}", urlencoding::encode(key)).as_str();
+    }
+
+    /// URL used by [`Synchronous::ping`] and [`Asynchronous::ping`]. (Listing a reserved prefix, which should be cheap)
+    fn ping_url(&self) -> String {
+        return self.config.url.as_str().to_string()
+            + format!("?prefix={}", urlencoding::encode(RESERVED_PREFIX)).as_str();
+    }
+
+    /// Prefix of keys used by [`Synchronous::bench`] and [`Asynchronous::bench`].
+    fn bench_prefix() -> String {
+        return format!("{}bench/", RESERVED_PREFIX);
+    }
+
+    /// Key used by [`Synchronous::bench`] and [`Asynchronous::bench`] for a round.
+    fn bench_key(round: usize) -> String {
+        return format!("{}{}", Self::bench_prefix(), round);
+    }
+
+    /// Sidecar key recording an applied idempotency key of `set_idempotent`.
+    fn idempotency_key(idempotency_key: &str) -> String {
+        return format!("{}idempotency/{}", RESERVED_PREFIX, idempotency_key);
+    }
+
+    /// Key and value written by [`Synchronous::measure_write_visibility`] and [`Asynchronous::measure_write_visibility`].
+    /// Unique per call, so concurrent probes (or a leftover from a crashed one) can't be mistaken for this one.
+    fn visibility_probe() -> (String, String) {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let token = format!("{}-{}", std::process::id(), nanos);
+        return (format!("{}visibility/{}", RESERVED_PREFIX, token), token);
+    }
+
+    /// Perform the first `ops` operations of a bench round, returning how many failed.
+    fn bench_round_blocking(&self, round: usize, ops: usize) -> usize {
+        let key = Self::bench_key(round);
+        let mut errors = 0;
+        if ops > 0 && Synchronous::set(self, &key, "bench").is_err() {
+            errors += 1;
+        }
+        if ops > 1 && Synchronous::get(self, &key).is_err() {
+            errors += 1;
+        }
+        if ops > 2 && Synchronous::delete(self, &key).is_err() {
+            errors += 1;
+        }
+        return errors;
+    }
+
+    /// Perform the first `ops` operations of a bench round, returning how many failed.
+    async fn bench_round(&self, round: usize, ops: usize) -> usize {
+        let key = Self::bench_key(round);
+        let mut errors = 0;
+        if ops > 0
+            && Asynchronous::set(self, key.as_str(), "bench")
+                .await
+                .is_err()
+        {
+            errors += 1;
+        }
+        if ops > 1 && Asynchronous::get(self, key.as_str()).await.is_err() {
+            errors += 1;
+        }
+        if ops > 2 && Asynchronous::delete(self, key.as_str()).await.is_err() {
+            errors += 1;
+        }
+        return errors;
+    }
+}
+
+#[allow(
+    clippy::needless_return,
+    clippy::unnecessary_unwrap,
+    clippy::redundant_field_names
+)]
+impl Synchronous for Database {
+    fn set(&self, key: impl AsRef<str>, value: impl AsRef<str>) -> Result<(), Error> {
+        reject_reserved_value(value.as_ref(), &self.config)?;
+        return self.write_value_blocking(key.as_ref(), value.as_ref());
+    }
+
+    fn set_with_receipt(
+        &self,
+        key: impl AsRef<str>,
+        value: impl AsRef<str>,
+    ) -> Result<WriteReceipt, Error> {
+        Synchronous::set(self, key.as_ref(), value.as_ref())?;
+        return Ok(WriteReceipt {
+            key: self.stored_key(key.as_ref()),
+            bytes: value.as_ref().len(),
+            at: std::time::SystemTime::now(),
+        });
+    }
+
+    fn set_idempotent(
+        &self,
+        key: impl AsRef<str>,
+        value: impl AsRef<str>,
+        idempotency_key: Option<&str>,
+    ) -> Result<bool, Error> {
+        let sidecar = match idempotency_key {
+            Some(idempotency_key) => Self::idempotency_key(idempotency_key),
+            None => {
+                Synchronous::set(self, key.as_ref(), value.as_ref())?;
+                return Ok(true);
+            }
+        };
+        if Synchronous::exists(self, &sidecar)? {
+            return Ok(false);
+        }
+        Synchronous::set(self, key.as_ref(), value.as_ref())?;
+        Synchronous::set(self, &sidecar, key.as_ref())?;
+        return Ok(true);
+    }
+
+    fn set_display<V: std::fmt::Display>(
+        &self,
+        key: impl AsRef<str>,
+        value: V,
+    ) -> Result<(), Error> {
+        return Synchronous::set(self, key.as_ref(), value.to_string().as_str());
+    }
+
+    #[cfg(feature = "compression")]
+    fn set_compressed(&self, key: impl AsRef<str>, value: impl AsRef<str>) -> Result<(), Error> {
+        let compressed = compress_value(value.as_ref())?;
+        return self.write_value_blocking(key.as_ref(), compressed.as_str());
+    }
+
+    fn get(&self, key: impl AsRef<str>) -> Result<String, Error> {
+        let key = self.stored_key(key.as_ref());
+        self.validate_key(key.as_ref())?;
+        let client = reqwest::blocking::Client::new();
+        let request = client.get(
+            self.config.url.as_str().to_string()
+                + format!("/{}", urlencoding::encode(key.as_ref())).as_str(),
+        );
+        let (response, _permit) = self.send_blocking(Operation::Get, key.as_ref(), request)?;
+        // println!("{:#?}", response); debugging
+        if !response.status().is_success() {
+            return Err(Error {
+                kind: ErrorKind::NoItemFoundError,
+                message: "No items were found on the database.".to_string(),
+            });
+        }
+        let content = read_value_blocking(response, self.config.max_value_bytes)?;
+        return self.finish_value(content);
+    }
+
+    fn get_nonempty_retry(
+        &self,
+        key: impl AsRef<str>,
+        max_retries: usize,
+    ) -> Result<String, Error> {
+        let mut attempt = 0;
+        loop {
+            let value = Synchronous::get(self, key.as_ref())?;
+            if !value.is_empty() || attempt >= max_retries {
+                return Ok(value);
+            }
+            attempt += 1;
+            std::thread::sleep(RETRY_DELAY * attempt as u32);
+        }
+    }
+
+    fn get_parsed<V: std::str::FromStr>(&self, key: impl AsRef<str>) -> Result<V, Error>
+    where
+        V::Err: std::fmt::Display,
+    {
+        let value = Synchronous::get(self, key.as_ref())?;
+        return parse_value(key.as_ref(), &value);
+    }
+
+    fn delete(&self, key: impl AsRef<str>) -> Result<(), Error> {
+        let key = self.stored_key(key.as_ref());
+        self.validate_key(key.as_ref())?;
+        let client = reqwest::blocking::Client::new();
+        let request = client.delete(
+            self.config.url.as_str().to_string()
+                + format!("/{}", urlencoding::encode(key.as_ref())).as_str(),
+        );
+        let (response, _permit) = self.send_blocking(Operation::Delete, key.as_ref(), request)?;
+        if !response.status().is_success() {
+            return Err(Error {
+                kind: ErrorKind::NoItemFoundError,
+                message: "No item with that name were found.".to_string(),
+            });
+        }
+        #[cfg(feature = "cache")]
+        self.cache_remove(key.as_ref());
+        return Ok(());
+    }
+
+    fn delete_verified(&self, key: impl AsRef<str>, max_retries: usize) -> Result<(), Error> {
+        Synchronous::delete(self, key.as_ref())?;
+        let mut attempt = 0;
+        loop {
+            if !Synchronous::exists(self, key.as_ref())? {
+                return Ok(());
+            }
+            if attempt >= max_retries {
+                return Err(still_exists(key.as_ref()));
+            }
+            attempt += 1;
+            std::thread::sleep(RETRY_DELAY * attempt as u32);
+        }
+    }
+    fn list(&self, prefix: Option<impl AsRef<str>>) -> Result<Vec<String>, Error> {
+        let prefix2 = match &prefix {
+            Some(p) => p.as_ref(),
+            None => "",
+        };
+        self.validate_prefix(prefix2)?;
+        return self.list_keys_blocking(prefix2);
+    }
+
+    fn list_prefix_ci(&self, prefix: impl AsRef<str>) -> Result<std::vec::Vec<String>, Error> {
+        self.validate_prefix(prefix.as_ref())?;
+        let keys = self.list_keys_blocking("")?;
+        return Ok(filter_prefix_ci(keys, prefix.as_ref()));
+    }
+
+    fn list_page(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        cursor: Option<&ListCursor>,
+        page_size: usize,
+    ) -> Result<(std::vec::Vec<String>, Option<ListCursor>), Error> {
+        ListCursor::check_page_size(page_size)?;
+        let keys = Synchronous::list(self, prefix)?;
+        return Ok(ListCursor::page(keys, cursor, page_size));
+    }
+
+    fn list_to_sender(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        tx: std::sync::mpsc::SyncSender<String>,
+    ) -> Result<(), Error> {
+        let prefix = prefix.as_ref().map(|p| p.as_ref()).unwrap_or("");
+        self.validate_prefix(prefix)?;
+        let client = reqwest::blocking::Client::new();
+        let request = client.get(self.list_url(prefix));
+        let (response, _permit) = self.send_blocking(Operation::List, prefix, request)?;
+        let mut reader = std::io::BufReader::new(response);
+        loop {
+            let mut line = std::vec::Vec::new();
+            let read = std::io::BufRead::read_until(&mut reader, b'\n', &mut line);
+            if read.is_err() {
+                return Err(Error {
+                    kind: ErrorKind::HttpError,
+                    message: read.unwrap_err().to_string(),
+                });
+            }
+            if read.unwrap() == 0 {
+                return Ok(());
+            }
+            if tx.send(key_from_line(line)?).is_err() {
+                return Ok(()); // receiver is gone
+            }
+        }
+    }
+    fn list_tree(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        delimiter: char,
+    ) -> Result<TreeNode, Error> {
+        let prefix = prefix.as_ref().map(|p| p.as_ref()).unwrap_or("");
+        let keys = Synchronous::list(self, Some(prefix))?;
+        return Ok(TreeNode::from_keys(prefix, keys, delimiter));
+    }
+
+    fn delete_prefix(&self, prefix: impl AsRef<str>) -> Result<usize, Error> {
+        let mut deleted = 0;
+        for key in Synchronous::list(self, Some(prefix))? {
+            let res = Synchronous::delete(self, &key);
+            match res {
+                Ok(()) => deleted += 1,
+                Err(e) => match e.kind {
+                    ErrorKind::NoItemFoundError => continue, // deleted after listing
+                    _ => return Err(e),
+                },
+            }
+        }
+        return Ok(deleted);
+    }
+
+    fn delete_where<F: Fn(&str, &str) -> bool>(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        f: F,
+    ) -> Result<usize, Error> {
+        let mut deleted = 0;
+        for key in Synchronous::list(self, prefix)? {
+            let value = match not_found_as_none(Synchronous::get(self, &key))? {
+                Some(value) => value,
+                None => continue, // deleted after listing
+            };
+            if !f(&key, &value) {
+                continue;
+            }
+            match Synchronous::delete(self, &key) {
+                Ok(()) => deleted += 1,
+                Err(e) => match e.kind {
+                    ErrorKind::NoItemFoundError => continue,
+                    _ => return Err(e),
+                },
+            }
+        }
+        return Ok(deleted);
+    }
+
+    fn list_expect_nonempty(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        max_retries: usize,
+    ) -> Result<std::vec::Vec<String>, Error> {
+        let prefix = prefix.as_ref().map(|p| p.as_ref());
+        let mut attempt = 0;
+        loop {
+            let keys = Synchronous::list(self, prefix)?;
+            if !keys.is_empty() || attempt >= max_retries {
+                return Ok(keys);
+            }
+            attempt += 1;
+            std::thread::sleep(RETRY_DELAY * attempt as u32);
+        }
+    }
+
+    fn list_checked(&self, prefix: Option<impl AsRef<str>>) -> Result<ListResult, Error> {
+        let prefix = prefix.as_ref().map(|p| p.as_ref()).unwrap_or("");
+        self.validate_prefix(prefix)?;
+        let client = reqwest::blocking::Client::new();
+        let request = client.get(self.list_url(prefix));
+        let (response, _permit) = self.send_blocking(Operation::List, prefix, request)?;
+        let announced_empty = announces_empty(response.status());
+        let content = response.text();
+        if content.is_err() {
+            return Err(Error {
+                kind: ErrorKind::DecodeError,
+                message: content.unwrap_err().to_string(),
+            });
+        }
+        let keys: std::vec::Vec<String> = content.unwrap().lines().map(String::from).collect();
+        return Ok(ListResult {
+            explicit_empty: keys.is_empty() && announced_empty,
+            keys: keys,
+        });
+    }
+
+    fn exists(&self, key: impl AsRef<str>) -> Result<bool, Error> {
+        let stored = self.stored_key(key.as_ref());
+        self.validate_key(&stored)?;
+        let client = reqwest::blocking::Client::new();
+        let (response, _) =
+            self.send_blocking(Operation::Head, &stored, client.head(self.key_url(&stored)))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        if !response.status().is_success() {
+            return Err(unexpected_status(response.status()));
+        }
+        return Ok(true);
+    }
+
+    fn ping(&self) -> PingStatus {
+        let client = reqwest::blocking::Client::new();
+        // bypasses the circuit breaker, a health check has to see the database as it is right now
+        let response = self.dispatch_blocking(
+            Operation::List,
+            &[RESERVED_PREFIX],
+            false,
+            client.get(self.ping_url()),
+        );
+        if response.is_err() {
+            return PingStatus::Unreachable;
+        }
+        return ping_status(response.unwrap().0.status());
+    }
+
+    fn get_many(&self, keys: &[impl AsRef<str>]) -> Result<std::vec::Vec<Option<String>>, Error> {
+        let mut fetched = std::collections::HashMap::new();
+        for key in unique_keys(keys) {
+            let value = not_found_as_none(Synchronous::get(self, key))?;
+            fetched.insert(key, value);
+        }
+        return Ok(keys
+            .iter()
+            .map(|key| fetched[key.as_ref()].clone())
+            .collect());
+    }
+
+    fn set_many(&self, pairs: &[(impl AsRef<str>, impl AsRef<str>)]) -> Result<(), Error> {
+        let client = reqwest::blocking::Client::new();
+        for (keys, body) in self.set_many_bodies(pairs)? {
+            let request = client
+                .post(self.config.url.as_str().to_string())
+                .body(body)
+                .header("Content-Type", "application/x-www-form-urlencoded");
+            let batch: std::vec::Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+            let res = self.dispatch_blocking(Operation::Set, &batch, true, request);
+            // invalidated even on failure, the server may have applied it anyway
+            #[cfg(feature = "cache")]
+            for key in &keys {
+                self.cache_remove(key);
+            }
+            res?;
+        }
+        return Ok(());
+    }
+
+    fn list_with_small_values(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        max_bytes: usize,
+    ) -> Result<std::vec::Vec<(String, Option<String>)>, Error> {
+        let client = reqwest::blocking::Client::new();
+        let mut pairs = std::vec::Vec::new();
+        for key in Synchronous::list(self, prefix)? {
+            let stored = self.stored_key(&key);
+            self.validate_key(&stored)?;
+            let url = self.key_url(&stored);
+            // HEAD has no body, its slot is given back right away for the GET
+            let (response, _) = self.send_blocking(Operation::Head, &stored, client.head(&url))?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                continue; // deleted after listing
+            }
+            if !response.status().is_success() {
+                return Err(unexpected_status(response.status()));
+            }
+            if header_content_length(response.headers()).unwrap_or(0) > max_bytes as u64 {
+                pairs.push((key, None));
+                continue;
+            }
+            let (response, _permit) =
+                self.send_blocking(Operation::Get, &stored, client.get(&url))?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(unexpected_status(response.status()));
+            }
+            let value = match too_large_as_none(read_value_blocking(response, Some(max_bytes)))? {
+                Some(value) => too_large_as_none(self.finish_value(value))?,
+                None => None,
+            };
+            pairs.push((key, value));
+        }
+        return Ok(pairs);
+    }
+
+    #[cfg(feature = "encoding")]
+    fn get_with_encoding(&self, key: impl AsRef<str>, label: &str) -> Result<String, Error> {
+        let encoding = encoding_for_label(label)?;
+        let (response, _permit) = self.open_value_blocking(key.as_ref())?;
+        let content = read_bytes_blocking(response, self.config.max_value_bytes)?;
+        return decode_with(encoding, &content);
+    }
+
+    fn get_with_metadata(&self, key: impl AsRef<str>) -> Result<ValueWithMetadata, Error> {
+        let (response, _permit) = self.open_value_blocking(key.as_ref())?;
+        let (last_modified, etag) = value_metadata(response.headers());
+        let value = read_value_blocking(response, self.config.max_value_bytes)?;
+        return Ok(ValueWithMetadata {
+            value: self.finish_value(value)?,
+            last_modified: last_modified,
+            etag: etag,
+        });
+    }
+
+    fn get_with_fallback(
+        &self,
+        primary: impl AsRef<str>,
+        fallback: impl AsRef<str>,
+    ) -> Result<Option<String>, Error> {
+        let value = not_found_as_none(Synchronous::get(self, primary))?;
+        if value.is_some() {
+            return Ok(value);
+        }
+        return not_found_as_none(Synchronous::get(self, fallback));
+    }
+
+    #[cfg(feature = "cache")]
+    fn get_swr(
+        &self,
+        key: impl AsRef<str>,
+        max_stale: std::time::Duration,
+    ) -> Result<String, Error> {
+        let key = &self.stored_key(key.as_ref());
+        if let Some((value, start_refresh)) = self.cache_lookup(key, max_stale) {
+            if start_refresh {
+                let db = self.clone();
+                let key = key.to_string();
+                let generation = self.cache_fetch_start(&key);
+                std::thread::spawn(move || {
+                    let res = Synchronous::get(&db, &key);
+                    db.cache_fetch_end(&key, generation, &res);
+                });
+            }
+            return Ok(value);
+        }
+        let generation = self.cache_fetch_start(key);
+        let res = Synchronous::get(self, key);
+        self.cache_fetch_end(key, generation, &res);
+        return res;
+    }
+
+    #[cfg(feature = "cache")]
+    fn get_shared(&self, key: impl AsRef<str>) -> Result<std::sync::Arc<str>, Error> {
+        let key = &self.stored_key(key.as_ref());
+        if let Some(value) = self.cache_shared(key) {
+            return Ok(value);
+        }
+        let generation = self.cache_fetch_start(key);
+        let res = Synchronous::get(self, key);
+        let cached = self.cache_fetch_end(key, generation, &res);
+        let value = res?;
+        return Ok(cached.unwrap_or_else(|| std::sync::Arc::from(value.as_str())));
+    }
+
+    fn clone_namespace(
+        &self,
+        from_prefix: impl AsRef<str>,
+        to_prefix: impl AsRef<str>,
+    ) -> Result<usize, Error> {
+        let from_prefix = from_prefix.as_ref();
+        let mut copied = 0;
+        for key in Synchronous::list(self, Some(from_prefix))? {
+            let value = match not_found_as_none(Synchronous::get(self, &key))? {
+                Some(value) => value,
+                None => continue, // deleted after listing
+            };
+            let new_key =
+                to_prefix.as_ref().to_string() + key.strip_prefix(from_prefix).unwrap_or(&key);
+            self.write_value_blocking(&new_key, &value)?;
+            copied += 1;
+        }
+        return Ok(copied);
+    }
+
+    fn copy_all_to(
+        &self,
+        dest: &Database,
+        prefix: Option<impl AsRef<str>>,
+        overwrite: bool,
+    ) -> Result<usize, Error> {
+        let mut copied = 0;
+        for key in Synchronous::list(self, prefix)? {
+            if !overwrite && Synchronous::exists(dest, &key)? {
+                continue;
+            }
+            let value = match not_found_as_none(Synchronous::get(self, &key))? {
+                Some(value) => value,
+                None => continue, // deleted after listing
+            };
+            dest.write_value_blocking(&key, &value)?;
+            copied += 1;
+        }
+        return Ok(copied);
+    }
+
+    fn consistency_check(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+    ) -> Result<ConsistencyReport, Error> {
+        let keys = Synchronous::list(self, prefix)?;
+        let mut report = ConsistencyReport {
+            listed: keys.len(),
+            missing_on_get: std::vec::Vec::new(),
+        };
+        for key in keys {
+            if !Synchronous::exists(self, &key)? {
+                report.missing_on_get.push(key);
+            }
+        }
+        report.missing_on_get.sort();
+        return Ok(report);
+    }
+
+    fn bench(&self, ops: usize, concurrency: usize) -> Result<BenchResult, Error> {
+        let rounds = ops.div_ceil(3);
+        let next_round = std::sync::atomic::AtomicUsize::new(0);
+        let errors = std::sync::atomic::AtomicUsize::new(0);
+        let start = std::time::Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..concurrency.max(1) {
+                scope.spawn(|| loop {
+                    let round = next_round.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    if round >= rounds {
+                        break;
+                    }
+                    let failed = self.bench_round_blocking(round, ops - round * 3);
+                    errors.fetch_add(failed, std::sync::atomic::Ordering::Relaxed);
+                });
+            }
+        });
+        let elapsed = start.elapsed();
+        for key in Synchronous::list(self, Some(Self::bench_prefix()))? {
+            Synchronous::delete(self, &key)?;
+        }
+        return Ok(BenchResult::new(ops, errors.into_inner(), elapsed));
+    }
+
+    fn measure_write_visibility(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<std::time::Duration, Error> {
+        let (key, token) = Self::visibility_probe();
+        Synchronous::set(self, &key, &token)?;
+        let start = std::time::Instant::now();
+        let res = loop {
+            match not_found_as_none(Synchronous::get(self, &key)) {
+                Ok(Some(value)) if value == token => break Ok(start.elapsed()),
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            }
+            if start.elapsed() >= timeout {
+                break Err(visibility_timeout(timeout));
+            }
+            std::thread::sleep(VISIBILITY_POLL_INTERVAL);
+        };
+        let _ = Synchronous::delete(self, &key);
+        return res;
+    }
+
+    fn with_snapshot<F, R>(&self, keys: &[&str], f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut std::collections::HashMap<String, String>) -> R,
+    {
+        let mut original = std::collections::HashMap::new();
+        for key in keys {
+            if let Some(value) = not_found_as_none(Synchronous::get(self, key))? {
+                original.insert(key.to_string(), value);
+            }
+        }
+        let mut snapshot = original.clone();
+        let output = f(&mut snapshot);
+        let changes = snapshot_changes(keys, &original, &snapshot);
+        for (i, (key, value)) in changes.iter().enumerate() {
+            let res = match value {
+                Some(value) => Synchronous::set(self, key, value),
+                None => Synchronous::delete(self, key),
+            };
+            if res.is_err() {
+                for (key, _) in changes[..i].iter().rev() {
+                    let _ = match original.get(key) {
+                        Some(value) => self.write_value_blocking(key, value),
+                        None => Synchronous::delete(self, key),
+                    };
+                }
+                return Err(res.unwrap_err());
+            }
+        }
+        return Ok(output);
+    }
+
+    #[cfg(feature = "serde")]
+    fn export_json(&self, prefix: Option<impl AsRef<str>>) -> Result<String, Error> {
+        let keys = Synchronous::list(self, prefix)?;
+        let mut object = serde_json::Map::new();
+        for key in keys {
+            let res = Synchronous::get(self, &key);
+            match res {
+                Ok(value) => {
+                    object.insert(key, serde_json::Value::String(value));
+                }
+                Err(e) => match e.kind {
+                    ErrorKind::NoItemFoundError => continue, // deleted after listing
+                    _ => return Err(e),
+                },
+            }
+        }
+        return Ok(serde_json::Value::Object(object).to_string());
+    }
+
+    #[cfg(feature = "serde")]
+    fn import_json(&self, json: &str, overwrite: bool) -> Result<usize, Error> {
+        let object = parse_json_object(json)?;
+        let mut written = 0;
+        for (key, value) in object {
+            if !overwrite && Synchronous::exists(self, &key)? {
+                continue;
+            }
+            self.write_value_blocking(&key, &json_value_to_string(value))?;
+            written += 1;
+        }
+        return Ok(written);
+    }
+
+    #[cfg(feature = "serde")]
+    fn set_json<V>(
+        &self,
+        key: impl AsRef<str>,
+        value: &V,
+        validator: Option<JsonValidator<V>>,
+    ) -> Result<(), Error>
+    where
+        V: serde::Serialize,
+    {
+        let value = to_json_validated(value, validator)?;
+        return Synchronous::set(self, key.as_ref(), value.as_str());
+    }
+
+    #[cfg(feature = "serde")]
+    fn get_json<V>(&self, key: impl AsRef<str>) -> Result<V, Error>
+    where
+        V: serde::de::DeserializeOwned,
+    {
+        let value = Synchronous::get(self, key.as_ref())?;
+        return parse_json_value(key.as_ref(), &value);
+    }
+
+    #[cfg(feature = "serde")]
+    fn get_json_array_stream<V>(
+        &self,
+        key: impl AsRef<str>,
+    ) -> impl Iterator<Item = Result<V, Error>> + '_
+    where
+        V: serde::de::DeserializeOwned,
+    {
+        let mut response = Some(self.open_value_blocking(key.as_ref()));
+        let mut parser = JsonArrayParser::new();
+        return std::iter::from_fn(move || {
+            let body = match response.as_mut()? {
+                Ok((body, _permit)) => body,
+                Err(_) => return response.take().unwrap().err().map(Err),
+            };
+            loop {
+                match parser.next_item::<V>() {
+                    JsonArrayItem::Item(item) => return Some(item),
+                    JsonArrayItem::Done => return None,
+                    JsonArrayItem::NeedMore => {}
+                }
+                let mut chunk = [0; 8192];
+                match std::io::Read::read(body, &mut chunk) {
+                    Ok(0) => parser.finish(),
+                    Ok(read) => parser.feed(&chunk[..read]),
+                    Err(e) => {
+                        response = None;
+                        return Some(Err(Error {
+                            kind: ErrorKind::HttpError,
+                            message: e.to_string(),
+                        }));
+                    }
+                }
+            }
+        });
+    }
+
+    #[cfg(feature = "serde")]
+    fn list_with_values_json<V>(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+        skip_malformed: bool,
+    ) -> Result<std::vec::Vec<(String, V)>, Error>
+    where
+        V: serde::de::DeserializeOwned,
+    {
+        let mut pairs = std::vec::Vec::new();
+        for key in Synchronous::list(self, prefix)? {
+            let value = match not_found_as_none(Synchronous::get(self, &key))? {
+                Some(value) => value,
+                None => continue, // deleted after listing
+            };
+            match parse_json_value::<V>(&key, &value) {
+                Ok(value) => pairs.push((key, value)),
+                Err(e) => {
+                    if !skip_malformed {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        return Ok(pairs);
+    }
+}
+
+#[async_trait::async_trait]
+#[allow(
+    clippy::needless_return,
+    clippy::unnecessary_unwrap,
+    clippy::redundant_field_names
+)]
+impl Asynchronous for Database {
+    async fn set<T>(&self, key: T, value: T) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        reject_reserved_value(value.as_ref(), &self.config)?;
+        return self.write_value(key.as_ref(), value.as_ref()).await;
+    }
+
+    async fn set_with_receipt<T>(&self, key: T, value: T) -> Result<WriteReceipt, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        Asynchronous::set(self, key.as_ref(), value.as_ref()).await?;
+        return Ok(WriteReceipt {
+            key: self.stored_key(key.as_ref()),
+            bytes: value.as_ref().len(),
+            at: std::time::SystemTime::now(),
+        });
+    }
+
+    async fn set_idempotent<T>(
+        &self,
+        key: T,
+        value: T,
+        idempotency_key: Option<&str>,
+    ) -> Result<bool, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let sidecar = match idempotency_key {
+            Some(idempotency_key) => Self::idempotency_key(idempotency_key),
+            None => {
+                Asynchronous::set(self, key.as_ref(), value.as_ref()).await?;
+                return Ok(true);
+            }
+        };
+        if Asynchronous::exists(self, sidecar.as_str()).await? {
+            return Ok(false);
+        }
+        Asynchronous::set(self, key.as_ref(), value.as_ref()).await?;
+        Asynchronous::set(self, sidecar.as_str(), key.as_ref()).await?;
+        return Ok(true);
+    }
+
+    async fn set_display<T, V>(&self, key: T, value: V) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+        V: std::fmt::Display + Send,
+    {
+        let value = value.to_string();
+        return Asynchronous::set(self, key.as_ref(), value.as_str()).await;
+    }
+
+    #[cfg(feature = "compression")]
+    async fn set_compressed<T>(&self, key: T, value: T) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let compressed = compress_value(value.as_ref())?;
+        return self.write_value(key.as_ref(), compressed.as_str()).await;
+    }
+
+    async fn get<T>(&self, key: T) -> Result<String, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let key = self.stored_key(key.as_ref());
+        self.validate_key(key.as_ref())?;
+        let client = reqwest::Client::new();
+        let request = client.get(
+            self.config.url.as_str().to_string()
+                + format!("/{}", urlencoding::encode(key.as_ref())).as_str(),
+        );
+        let (response, _permit) = self.send(Operation::Get, key.as_ref(), request).await?;
+        if !response.status().is_success() {
+            return Err(Error {
+                kind: ErrorKind::NoItemFoundError,
+                message: "No items were found on the database.".to_string(),
+            });
+        }
+        let content = read_value(response, self.config.max_value_bytes).await?;
+        return self.finish_value(content);
+    }
+
+    async fn get_nonempty_retry<T>(&self, key: T, max_retries: usize) -> Result<String, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let mut attempt = 0;
+        loop {
+            let value = Asynchronous::get(self, key.as_ref()).await?;
+            if !value.is_empty() || attempt >= max_retries {
+                return Ok(value);
+            }
+            attempt += 1;
+            sleep(RETRY_DELAY * attempt as u32).await;
+        }
+    }
+
+    async fn get_parsed<V, T>(&self, key: T) -> Result<V, Error>
+    where
+        V: std::str::FromStr,
+        V::Err: std::fmt::Display,
+        T: AsRef<str> + Send,
+    {
+        let value = Asynchronous::get(self, key.as_ref()).await?;
+        return parse_value(key.as_ref(), &value);
+    }
+
+    async fn delete<T>(&self, key: T) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let key = self.stored_key(key.as_ref());
+        self.validate_key(key.as_ref())?;
+        let client = reqwest::Client::new();
+        let request = client.delete(
+            self.config.url.as_str().to_string()
+                + format!("/{}", urlencoding::encode(key.as_ref())).as_str(),
+        );
+        let (response, _permit) = self.send(Operation::Delete, key.as_ref(), request).await?;
+        if !response.status().is_success() {
+            return Err(Error {
+                kind: ErrorKind::NoItemFoundError,
+                message: "No item with that name were found.".to_string(),
+            });
+        }
+        #[cfg(feature = "cache")]
+        self.cache_remove(key.as_ref());
+        return Ok(());
+    }
+
+    async fn delete_verified<T>(&self, key: T, max_retries: usize) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        Asynchronous::delete(self, key.as_ref()).await?;
+        let mut attempt = 0;
+        loop {
+            if !Asynchronous::exists(self, key.as_ref()).await? {
+                return Ok(());
+            }
+            if attempt >= max_retries {
+                return Err(still_exists(key.as_ref()));
+            }
+            attempt += 1;
+            sleep(RETRY_DELAY * attempt as u32).await;
+        }
+    }
+    async fn list<T>(&self, prefix: Option<T>) -> Result<Vec<String>, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let prefix2 = match &prefix {
+            Some(p) => p.as_ref(),
+            None => "",
+        };
+        self.validate_prefix(prefix2)?;
+        return self.list_keys(prefix2).await;
+    }
+
+    async fn list_prefix_ci<T>(&self, prefix: T) -> Result<std::vec::Vec<String>, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        self.validate_prefix(prefix.as_ref())?;
+        let keys = self.list_keys("").await?;
+        return Ok(filter_prefix_ci(keys, prefix.as_ref()));
+    }
+
+    async fn list_page<T>(
+        &self,
+        prefix: Option<T>,
+        cursor: Option<&ListCursor>,
+        page_size: usize,
+    ) -> Result<(std::vec::Vec<String>, Option<ListCursor>), Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        ListCursor::check_page_size(page_size)?;
+        let keys = Asynchronous::list(self, prefix).await?;
+        return Ok(ListCursor::page(keys, cursor, page_size));
+    }
+
+    async fn list_to_sender<T>(
+        &self,
+        prefix: Option<T>,
+        mut tx: futures::channel::mpsc::Sender<String>,
+    ) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let prefix = prefix.as_ref().map(|p| p.as_ref()).unwrap_or("");
+        self.validate_prefix(prefix)?;
+        let client = reqwest::Client::new();
+        let request = client.get(self.list_url(prefix));
+        let (mut response, _permit) = self.send(Operation::List, prefix, request).await?;
+        let mut buffer = std::vec::Vec::new();
+        loop {
+            let chunk = response.chunk().await;
+            if chunk.is_err() {
+                return Err(Error {
+                    kind: ErrorKind::HttpError,
+                    message: chunk.unwrap_err().to_string(),
+                });
+            }
+            match chunk.unwrap() {
+                Some(chunk) => buffer.extend_from_slice(&chunk),
+                None => {
+                    if !buffer.is_empty() {
+                        let _ = tx.send(key_from_line(buffer)?).await;
+                    }
+                    return Ok(());
+                }
+            }
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let line: std::vec::Vec<u8> = buffer.drain(..=end).collect();
+                if tx.send(key_from_line(line)?).await.is_err() {
+                    return Ok(()); // receiver is gone
+                }
+            }
+        }
+    }
+
+    async fn list_into_sink<T, S>(&self, prefix: Option<T>, sink: S) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+        S: futures::Sink<String> + Send,
+        S::Error: std::fmt::Display,
+    {
+        let prefix = prefix.as_ref().map(|p| p.as_ref()).unwrap_or("");
+        self.validate_prefix(prefix)?;
+        let client = reqwest::Client::new();
+        let request = client.get(self.list_url(prefix));
+        let (mut response, _permit) = self.send(Operation::List, prefix, request).await?;
+        let mut sink = std::pin::pin!(sink);
+        let mut buffer = std::vec::Vec::new();
+        loop {
+            let chunk = response.chunk().await;
+            if chunk.is_err() {
+                return Err(Error {
+                    kind: ErrorKind::HttpError,
+                    message: chunk.unwrap_err().to_string(),
+                });
+            }
+            let mut lines = std::vec::Vec::new();
+            let done = match chunk.unwrap() {
+                Some(chunk) => {
+                    buffer.extend_from_slice(&chunk);
+                    false
+                }
+                None => true,
+            };
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                lines.push(buffer.drain(..=end).collect::<std::vec::Vec<u8>>());
+            }
+            if done && !buffer.is_empty() {
+                lines.push(std::mem::take(&mut buffer));
+            }
+            for line in lines {
+                let fed = sink.feed(key_from_line(line)?).await;
+                if fed.is_err() {
+                    return Err(Error {
+                        kind: ErrorKind::SinkError,
+                        message: fed.unwrap_err().to_string(),
+                    });
+                }
+            }
+            if done {
+                let closed = sink.close().await;
+                if closed.is_err() {
+                    return Err(Error {
+                        kind: ErrorKind::SinkError,
+                        message: closed.unwrap_err().to_string(),
+                    });
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    async fn list_tree<T>(&self, prefix: Option<T>, delimiter: char) -> Result<TreeNode, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let prefix = prefix.as_ref().map(|p| p.as_ref()).unwrap_or("");
+        let keys = Asynchronous::list(self, Some(prefix)).await?;
+        return Ok(TreeNode::from_keys(prefix, keys, delimiter));
+    }
+
+    async fn delete_prefix<T>(&self, prefix: T) -> Result<usize, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let keys = Asynchronous::list(self, Some(prefix)).await?;
+        let results = futures::stream::iter(keys)
+            .map(|key| async move { Asynchronous::delete(self, key.as_str()).await })
+            .buffer_unordered(MAX_CONCURRENCY)
+            .collect::<std::vec::Vec<_>>()
+            .await;
+        let mut deleted = 0;
+        for res in results {
+            match res {
+                Ok(()) => deleted += 1,
+                Err(e) => match e.kind {
+                    ErrorKind::NoItemFoundError => continue, // deleted after listing
+                    _ => return Err(e),
+                },
+            }
+        }
+        return Ok(deleted);
+    }
+
+    async fn delete_where<T, F>(&self, prefix: Option<T>, f: F) -> Result<usize, Error>
+    where
+        T: AsRef<str> + Send,
+        F: Fn(&str, &str) -> bool + Send + Sync,
+    {
+        let keys = Asynchronous::list(self, prefix).await?;
+        let f = &f;
+        let results = futures::stream::iter(keys)
+            .map(|key| async move {
+                let value = match not_found_as_none(Asynchronous::get(self, key.as_str()).await)? {
+                    Some(value) => value,
+                    None => return Ok(false), // deleted after listing
+                };
+                if !f(&key, &value) {
+                    return Ok(false);
+                }
+                match Asynchronous::delete(self, key.as_str()).await {
+                    Ok(()) => return Ok(true),
+                    Err(e) => match e.kind {
+                        ErrorKind::NoItemFoundError => return Ok(false),
+                        _ => return Err(e),
+                    },
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENCY)
+            .collect::<std::vec::Vec<Result<bool, Error>>>()
+            .await;
+        let mut deleted = 0;
+        for res in results {
+            if res? {
+                deleted += 1;
+            }
+        }
+        return Ok(deleted);
+    }
+
+    async fn list_expect_nonempty<T>(
+        &self,
+        prefix: Option<T>,
+        max_retries: usize,
+    ) -> Result<std::vec::Vec<String>, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let prefix = prefix.as_ref().map(|p| p.as_ref());
+        let mut attempt = 0;
+        loop {
+            let keys = Asynchronous::list(self, prefix).await?;
+            if !keys.is_empty() || attempt >= max_retries {
+                return Ok(keys);
+            }
+            attempt += 1;
+            sleep(RETRY_DELAY * attempt as u32).await;
+        }
+    }
+
+    async fn list_checked<T>(&self, prefix: Option<T>) -> Result<ListResult, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let prefix = prefix.as_ref().map(|p| p.as_ref()).unwrap_or("");
+        self.validate_prefix(prefix)?;
+        let client = reqwest::Client::new();
+        let request = client.get(self.list_url(prefix));
+        let (response, _permit) = self.send(Operation::List, prefix, request).await?;
+        let announced_empty = announces_empty(response.status());
+        let content = response.text().await;
+        if content.is_err() {
+            return Err(Error {
+                kind: ErrorKind::DecodeError,
+                message: content.unwrap_err().to_string(),
+            });
+        }
+        let keys: std::vec::Vec<String> = content.unwrap().lines().map(String::from).collect();
+        return Ok(ListResult {
+            explicit_empty: keys.is_empty() && announced_empty,
+            keys: keys,
+        });
+    }
+
+    async fn exists<T>(&self, key: T) -> Result<bool, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let stored = self.stored_key(key.as_ref());
+        self.validate_key(&stored)?;
+        let client = reqwest::Client::new();
+        let (response, _) = self
+            .send(Operation::Head, &stored, client.head(self.key_url(&stored)))
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        if !response.status().is_success() {
+            return Err(unexpected_status(response.status()));
+        }
+        return Ok(true);
+    }
+
+    async fn ping(&self) -> PingStatus {
+        let client = reqwest::Client::new();
+        // bypasses the circuit breaker, a health check has to see the database as it is right now
+        let response = self
+            .dispatch(
+                Operation::List,
+                &[RESERVED_PREFIX],
+                false,
+                client.get(self.ping_url()),
+            )
+            .await;
+        if response.is_err() {
+            return PingStatus::Unreachable;
+        }
+        return ping_status(response.unwrap().0.status());
+    }
+
+    async fn get_many<T>(&self, keys: &[T]) -> Result<std::vec::Vec<Option<String>>, Error>
+    where
+        T: AsRef<str> + Sync,
+    {
+        let unique: std::vec::Vec<String> = unique_keys(keys)
+            .into_iter()
+            .map(|key| key.to_string())
+            .collect();
+        let results = futures::stream::iter(unique)
+            .map(|key| async move {
+                let value = not_found_as_none(Asynchronous::get(self, key.as_str()).await);
+                return (key, value);
+            })
+            .buffer_unordered(MAX_CONCURRENCY)
+            .collect::<std::vec::Vec<_>>()
+            .await;
+        let mut fetched = std::collections::HashMap::new();
+        for (key, value) in results {
+            fetched.insert(key, value?);
+        }
+        return Ok(keys
+            .iter()
+            .map(|key| fetched[key.as_ref()].clone())
+            .collect());
+    }
+
+    async fn set_many<K, V>(&self, pairs: &[(K, V)]) -> Result<(), Error>
+    where
+        K: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        let client = reqwest::Client::new();
+        for (keys, body) in self.set_many_bodies(pairs)? {
+            let request = client
+                .post(self.config.url.as_str().to_string())
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body(body);
+            let batch: std::vec::Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+            let res = self.dispatch(Operation::Set, &batch, true, request).await;
+            // invalidated even on failure, the server may have applied it anyway
+            #[cfg(feature = "cache")]
+            for key in &keys {
+                self.cache_remove(key);
+            }
+            res?;
+        }
+        return Ok(());
+    }
+
+    async fn list_with_small_values<T>(
+        &self,
+        prefix: Option<T>,
+        max_bytes: usize,
+    ) -> Result<std::vec::Vec<(String, Option<String>)>, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let client = reqwest::Client::new();
+        let mut pairs = std::vec::Vec::new();
+        for key in Asynchronous::list(self, prefix).await? {
+            let stored = self.stored_key(&key);
+            self.validate_key(&stored)?;
+            let url = self.key_url(&stored);
+            // HEAD has no body, its slot is given back right away for the GET
+            let (response, _) = self
+                .send(Operation::Head, &stored, client.head(&url))
+                .await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                continue; // deleted after listing
+            }
+            if !response.status().is_success() {
+                return Err(unexpected_status(response.status()));
+            }
+            if header_content_length(response.headers()).unwrap_or(0) > max_bytes as u64 {
+                pairs.push((key, None));
+                continue;
+            }
+            let (response, _permit) = self.send(Operation::Get, &stored, client.get(&url)).await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(unexpected_status(response.status()));
+            }
+            let value = match too_large_as_none(read_value(response, Some(max_bytes)).await)? {
+                Some(value) => too_large_as_none(self.finish_value(value))?,
+                None => None,
+            };
+            pairs.push((key, value));
+        }
+        return Ok(pairs);
+    }
+
+    #[cfg(feature = "encoding")]
+    async fn get_with_encoding<T>(&self, key: T, label: &str) -> Result<String, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let encoding = encoding_for_label(label)?;
+        let (response, _permit) = self.open_value(key.as_ref()).await?;
+        let content = read_bytes(response, self.config.max_value_bytes).await?;
+        return decode_with(encoding, &content);
+    }
+
+    async fn get_with_metadata<T>(&self, key: T) -> Result<ValueWithMetadata, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let (response, _permit) = self.open_value(key.as_ref()).await?;
+        let (last_modified, etag) = value_metadata(response.headers());
+        let value = read_value(response, self.config.max_value_bytes).await?;
+        return Ok(ValueWithMetadata {
+            value: self.finish_value(value)?,
+            last_modified: last_modified,
+            etag: etag,
+        });
+    }
+
+    async fn get_with_fallback<T>(&self, primary: T, fallback: T) -> Result<Option<String>, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let value = not_found_as_none(Asynchronous::get(self, primary).await)?;
+        if value.is_some() {
+            return Ok(value);
+        }
+        return not_found_as_none(Asynchronous::get(self, fallback).await);
+    }
+
+    #[cfg(feature = "cache")]
+    async fn get_swr<T>(&self, key: T, max_stale: std::time::Duration) -> Result<String, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let key = &self.stored_key(key.as_ref());
+        if let Some((value, start_refresh)) = self.cache_lookup(key, max_stale) {
+            if start_refresh {
+                let db = self.clone();
+                let key = key.to_string();
+                let generation = self.cache_fetch_start(&key);
+                tokio::spawn(async move {
+                    let res = Asynchronous::get(&db, key.as_str()).await;
+                    db.cache_fetch_end(&key, generation, &res);
+                });
+            }
+            return Ok(value);
+        }
+        let generation = self.cache_fetch_start(key);
+        let res = Asynchronous::get(self, key).await;
+        self.cache_fetch_end(key, generation, &res);
+        return res;
+    }
+
+    #[cfg(feature = "cache")]
+    async fn get_shared<T>(&self, key: T) -> Result<std::sync::Arc<str>, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let key = &self.stored_key(key.as_ref());
+        if let Some(value) = self.cache_shared(key) {
+            return Ok(value);
+        }
+        let generation = self.cache_fetch_start(key);
+        let res = Asynchronous::get(self, key).await;
+        let cached = self.cache_fetch_end(key, generation, &res);
+        let value = res?;
+        return Ok(cached.unwrap_or_else(|| std::sync::Arc::from(value.as_str())));
+    }
+
+    async fn clone_namespace<T>(&self, from_prefix: T, to_prefix: T) -> Result<usize, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let from_prefix = from_prefix.as_ref();
+        let mut copied = 0;
+        for key in Asynchronous::list(self, Some(from_prefix)).await? {
+            let value = match not_found_as_none(Asynchronous::get(self, key.as_str()).await)? {
+                Some(value) => value,
+                None => continue, // deleted after listing
+            };
+            let new_key =
+                to_prefix.as_ref().to_string() + key.strip_prefix(from_prefix).unwrap_or(&key);
+            self.write_value(&new_key, &value).await?;
+            copied += 1;
+        }
+        return Ok(copied);
+    }
+
+    async fn copy_all_to<T>(
+        &self,
+        dest: &Database,
+        prefix: Option<T>,
+        overwrite: bool,
+    ) -> Result<usize, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let keys = Asynchronous::list(self, prefix).await?;
+        let results = futures::stream::iter(keys)
+            .map(|key| async move {
+                if !overwrite && Asynchronous::exists(dest, key.as_str()).await? {
+                    return Ok(false);
+                }
+                let value = match not_found_as_none(Asynchronous::get(self, key.as_str()).await)? {
+                    Some(value) => value,
+                    None => return Ok(false), // deleted after listing
+                };
+                dest.write_value(&key, &value).await?;
+                return Ok(true);
+            })
+            .buffer_unordered(MAX_CONCURRENCY)
+            .collect::<std::vec::Vec<Result<bool, Error>>>()
+            .await;
+        let mut copied = 0;
+        for res in results {
+            if res? {
+                copied += 1;
+            }
+        }
+        return Ok(copied);
+    }
+
+    async fn consistency_check<T>(&self, prefix: Option<T>) -> Result<ConsistencyReport, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let keys = Asynchronous::list(self, prefix).await?;
+        let mut report = ConsistencyReport {
+            listed: keys.len(),
+            missing_on_get: std::vec::Vec::new(),
+        };
+        let results = futures::stream::iter(keys)
+            .map(|key| async move {
+                let exists = Asynchronous::exists(self, key.as_str()).await;
+                return (key, exists);
+            })
+            .buffer_unordered(MAX_CONCURRENCY)
+            .collect::<std::vec::Vec<_>>()
+            .await;
+        for (key, exists) in results {
+            if !exists? {
+                report.missing_on_get.push(key);
+            }
+        }
+        report.missing_on_get.sort();
+        return Ok(report);
+    }
+
+    async fn bench(&self, ops: usize, concurrency: usize) -> Result<BenchResult, Error> {
+        let rounds = ops.div_ceil(3);
+        let start = std::time::Instant::now();
+        let errors = futures::stream::iter(0..rounds)
+            .map(|round| self.bench_round(round, ops - round * 3))
+            .buffer_unordered(concurrency.max(1))
+            .fold(0, |total, failed| async move { total + failed })
+            .await;
+        let elapsed = start.elapsed();
+        for key in Asynchronous::list(self, Some(Self::bench_prefix())).await? {
+            Asynchronous::delete(self, key.as_str()).await?;
+        }
+        return Ok(BenchResult::new(ops, errors, elapsed));
+    }
+
+    async fn measure_write_visibility(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<std::time::Duration, Error> {
+        let (key, token) = Self::visibility_probe();
+        Asynchronous::set(self, key.as_str(), token.as_str()).await?;
+        let start = std::time::Instant::now();
+        let res = loop {
+            match not_found_as_none(Asynchronous::get(self, key.as_str()).await) {
+                Ok(Some(value)) if value == token => break Ok(start.elapsed()),
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            }
+            if start.elapsed() >= timeout {
+                break Err(visibility_timeout(timeout));
+            }
+            sleep(VISIBILITY_POLL_INTERVAL).await;
+        };
+        let _ = Asynchronous::delete(self, key.as_str()).await;
+        return res;
+    }
+
+    async fn with_snapshot<F, R>(&self, keys: &[&str], f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut std::collections::HashMap<String, String>) -> R + Send,
+        R: Send,
+    {
+        let mut original = std::collections::HashMap::new();
+        for key in keys {
+            if let Some(value) = not_found_as_none(Asynchronous::get(self, *key).await)? {
+                original.insert(key.to_string(), value);
+            }
+        }
+        let mut snapshot = original.clone();
+        let output = f(&mut snapshot);
+        let changes = snapshot_changes(keys, &original, &snapshot);
+        for (i, (key, value)) in changes.iter().enumerate() {
+            let res = match value {
+                Some(value) => Asynchronous::set(self, key.as_str(), value.as_str()).await,
+                None => Asynchronous::delete(self, key.as_str()).await,
+            };
+            if res.is_err() {
+                for (key, _) in changes[..i].iter().rev() {
+                    let _ = match original.get(key) {
+                        Some(value) => self.write_value(key, value).await,
+                        None => Asynchronous::delete(self, key.as_str()).await,
+                    };
+                }
+                return Err(res.unwrap_err());
+            }
+        }
+        return Ok(output);
+    }
+
+    #[cfg(feature = "serde")]
+    async fn export_json<T>(&self, prefix: Option<T>) -> Result<String, Error>
+    where
+        T: AsRef<str> + Send,
+    {
+        let keys = Asynchronous::list(self, prefix).await?;
+        let mut object = serde_json::Map::new();
+        for key in keys {
+            let res = Asynchronous::get(self, key.as_str()).await;
+            match res {
+                Ok(value) => {
+                    object.insert(key, serde_json::Value::String(value));
+                }
+                Err(e) => match e.kind {
+                    ErrorKind::NoItemFoundError => continue, // deleted after listing
+                    _ => return Err(e),
+                },
+            }
+        }
+        return Ok(serde_json::Value::Object(object).to_string());
+    }
+
+    #[cfg(feature = "serde")]
+    async fn import_json(&self, json: &str, overwrite: bool) -> Result<usize, Error> {
+        let object = parse_json_object(json)?;
+        let mut written = 0;
+        for (key, value) in object {
+            if !overwrite && Asynchronous::exists(self, key.as_str()).await? {
+                continue;
+            }
+            self.write_value(&key, &json_value_to_string(value)).await?;
+            written += 1;
+        }
+        return Ok(written);
+    }
+
+    #[cfg(feature = "serde")]
+    async fn set_json<T, V>(
+        &self,
+        key: T,
+        value: &V,
+        validator: Option<JsonValidator<V>>,
+    ) -> Result<(), Error>
+    where
+        T: AsRef<str> + Send,
+        V: serde::Serialize + Sync,
+    {
+        let value = to_json_validated(value, validator)?;
+        return Asynchronous::set(self, key.as_ref(), value.as_str()).await;
+    }
+
+    #[cfg(feature = "serde")]
+    async fn get_json<T, V>(&self, key: T) -> Result<V, Error>
+    where
+        T: AsRef<str> + Send,
+        V: serde::de::DeserializeOwned,
+    {
+        let value = Asynchronous::get(self, key.as_ref()).await?;
+        return parse_json_value(key.as_ref(), &value);
+    }
+
+    #[cfg(feature = "serde")]
+    fn get_json_array_stream<T, V>(
+        &self,
+        key: T,
+    ) -> impl futures::Stream<Item = Result<V, Error>> + Send + '_
+    where
+        T: AsRef<str> + Send,
+        V: serde::de::DeserializeOwned + Send,
+    {
+        let key = key.as_ref().to_string();
+        let state = (
+            None::<(reqwest::Response, RequestPermit)>,
+            JsonArrayParser::new(),
+            false,
+        );
+        return futures::stream::unfold(state, move |(response, mut parser, done)| {
+            let key = key.clone();
+            async move {
+                if done {
+                    return None;
+                }
+                let (mut response, permit) = match response {
+                    Some(response) => response,
+                    None => match self.open_value(&key).await {
+                        Ok(response) => response,
+                        Err(e) => return Some((Err(e), (None, parser, true))),
+                    },
+                };
+                loop {
+                    match parser.next_item::<V>() {
+                        JsonArrayItem::Item(item) => {
+                            let done = item.is_err();
+                            return Some((item, (Some((response, permit)), parser, done)));
+                        }
+                        JsonArrayItem::Done => return None,
+                        JsonArrayItem::NeedMore => {}
+                    }
+                    match response.chunk().await {
+                        Ok(Some(chunk)) => parser.feed(&chunk),
+                        Ok(None) => parser.finish(),
+                        Err(e) => {
+                            let e = Error {
+                                kind: ErrorKind::HttpError,
+                                message: e.to_string(),
+                            };
+                            return Some((Err(e), (None, parser, true)));
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    #[cfg(feature = "serde")]
+    async fn list_with_values_json<T, V>(
+        &self,
+        prefix: Option<T>,
+        skip_malformed: bool,
+    ) -> Result<std::vec::Vec<(String, V)>, Error>
+    where
+        T: AsRef<str> + Send,
+        V: serde::de::DeserializeOwned + Send,
+    {
+        let mut pairs = std::vec::Vec::new();
+        for key in Asynchronous::list(self, prefix).await? {
+            let value = match not_found_as_none(Asynchronous::get(self, key.as_str()).await)? {
+                Some(value) => value,
+                None => continue, // deleted after listing
+            };
+            match parse_json_value::<V>(&key, &value) {
+                Ok(value) => pairs.push((key, value)),
+                Err(e) => {
+                    if !skip_malformed {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        return Ok(pairs);
+    }
+}
+
+#[cfg(feature = "blocking-adapter")]
+#[allow(clippy::needless_return)]
+impl BlockingAdapter {
+    /// Run any closure using the [`Synchronous`] API on the blocking thread pool. (e.g. `adapter.run(|db| db.list_prefix_ci("user")).await`)
+    /// Panics in the closure are passed on to the caller.
+    pub async fn run<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&Database) -> Result<R, Error> + Send + 'static,
+        R: Send + 'static,
+    {
+        let db = self.db.clone();
+        let task = tokio::task::spawn_blocking(move || f(&db));
+        match task.await {
+            Ok(result) => return result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /// [`Synchronous::set`] on the blocking thread pool.
+    pub async fn set(&self, key: impl AsRef<str>, value: impl AsRef<str>) -> Result<(), Error> {
+        let key = key.as_ref().to_string();
+        let value = value.as_ref().to_string();
+        return self.run(move |db| Synchronous::set(db, key, value)).await;
+    }
+
+    /// [`Synchronous::get`] on the blocking thread pool.
+    pub async fn get(&self, key: impl AsRef<str>) -> Result<String, Error> {
+        let key = key.as_ref().to_string();
+        return self.run(move |db| Synchronous::get(db, key)).await;
+    }
+
+    /// [`Synchronous::delete`] on the blocking thread pool.
+    pub async fn delete(&self, key: impl AsRef<str>) -> Result<(), Error> {
+        let key = key.as_ref().to_string();
+        return self.run(move |db| Synchronous::delete(db, key)).await;
+    }
+
+    /// [`Synchronous::list`] on the blocking thread pool.
+    pub async fn list(
+        &self,
+        prefix: Option<impl AsRef<str>>,
+    ) -> Result<std::vec::Vec<String>, Error> {
+        let prefix = prefix.map(|p| p.as_ref().to_string());
+        return self.run(move |db| Synchronous::list(db, prefix)).await;
+    }
+
+    /// [`Synchronous::exists`] on the blocking thread pool.
+    pub async fn exists(&self, key: impl AsRef<str>) -> Result<bool, Error> {
+        let key = key.as_ref().to_string();
+        return self.run(move |db| Synchronous::exists(db, key)).await;
+    }
+}
+
+#[cfg(feature = "tower")]
+#[allow(clippy::needless_return)]
+impl<R> tower_service::Service<R> for HealthService {
+    type Response = PingStatus;
+    type Error = std::convert::Infallible;
+    type Future = HealthFuture;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        return std::task::Poll::Ready(Ok(()));
+    }
+
+    fn call(&mut self, _request: R) -> Self::Future {
+        let db = self.db.clone();
+        return std::boxed::Box::pin(async move { return Ok(Asynchronous::ping(&db).await) });
+    }
+}
+
+/// Error for a value going over [`Config::with_max_value_bytes`].
+#[allow(clippy::needless_return)]
+fn value_too_large(max_value_bytes: usize) -> Error {
+    return Error {
+        kind: ErrorKind::ValueTooLargeError,
+        message: format!("Value is larger than {} bytes.", max_value_bytes),
+    };
+}
+
+/// Read a value's body, stopping early if it goes over `max_value_bytes`.
+#[allow(clippy::needless_return, clippy::unnecessary_unwrap)]
+fn read_value_blocking(
+    response: reqwest::blocking::Response,
+    max_value_bytes: Option<usize>,
+) -> Result<String, Error> {
+    if max_value_bytes.is_none() {
+        let content = response.text();
+        if content.is_err() {
+            return Err(Error {
+                kind: ErrorKind::HttpError,
+                message: content.unwrap_err().to_string(),
+            });
+        }
+        return Ok(content.unwrap());
+    }
+    let content = read_bytes_blocking(response, max_value_bytes)?;
+    return Ok(String::from_utf8_lossy(&content).into_owned());
+}
+
+/// Read a value's body, stopping early if it goes over `max_value_bytes`.
+#[allow(clippy::needless_return, clippy::unnecessary_unwrap)]
+async fn read_value(
+    response: reqwest::Response,
+    max_value_bytes: Option<usize>,
+) -> Result<String, Error> {
+    if max_value_bytes.is_none() {
+        let content = response.text().await;
+        if content.is_err() {
+            return Err(Error {
+                kind: ErrorKind::HttpError,
+                message: content.unwrap_err().to_string(),
+            });
+        }
+        return Ok(content.unwrap());
+    }
+    let content = read_bytes(response, max_value_bytes).await?;
+    return Ok(String::from_utf8_lossy(&content).into_owned());
+}
+
+/// Read a value's raw body, stopping early if it goes over `max_value_bytes`.
+#[allow(clippy::needless_return, clippy::unnecessary_unwrap)]
+fn read_bytes_blocking(
+    response: reqwest::blocking::Response,
+    max_value_bytes: Option<usize>,
+) -> Result<std::vec::Vec<u8>, Error> {
+    let limit = match max_value_bytes {
+        Some(max_value_bytes) => max_value_bytes as u64 + 1,
+        None => u64::MAX,
+    };
+    if let Some(max_value_bytes) = max_value_bytes {
+        if response.content_length().unwrap_or(0) > max_value_bytes as u64 {
+            return Err(value_too_large(max_value_bytes));
+        }
+    }
+    let mut content = std::vec::Vec::new();
+    let res = std::io::Read::read_to_end(&mut std::io::Read::take(response, limit), &mut content);
+    if res.is_err() {
+        return Err(Error {
+            kind: ErrorKind::HttpError,
+            message: res.unwrap_err().to_string(),
+        });
+    }
+    if let Some(max_value_bytes) = max_value_bytes {
+        if content.len() > max_value_bytes {
+            return Err(value_too_large(max_value_bytes));
+        }
+    }
+    return Ok(content);
+}
+
+/// Read a value's raw body, stopping early if it goes over `max_value_bytes`.
+#[allow(clippy::needless_return, clippy::unnecessary_unwrap)]
+async fn read_bytes(
+    mut response: reqwest::Response,
+    max_value_bytes: Option<usize>,
+) -> Result<std::vec::Vec<u8>, Error> {
+    let limit = max_value_bytes.unwrap_or(usize::MAX);
+    if response.content_length().unwrap_or(0) > limit as u64 {
+        return Err(value_too_large(limit));
+    }
+    let mut content = std::vec::Vec::new();
+    loop {
+        let chunk = response.chunk().await;
+        if chunk.is_err() {
+            return Err(Error {
+                kind: ErrorKind::HttpError,
+                message: chunk.unwrap_err().to_string(),
+            });
+        }
+        match chunk.unwrap() {
+            Some(chunk) => content.extend_from_slice(&chunk),
+            None => break,
+        }
+        if content.len() > limit {
+            return Err(value_too_large(limit));
+        }
+    }
+    return Ok(content);
+}
+
+/// Look up an encoding for `get_with_encoding`.
+#[cfg(feature = "encoding")]
+#[allow(clippy::needless_return)]
+fn encoding_for_label(label: &str) -> Result<&'static encoding_rs::Encoding, Error> {
+    match encoding_rs::Encoding::for_label(label.as_bytes()) {
+        Some(encoding) => return Ok(encoding),
+        None => {
+            return Err(Error {
+                kind: ErrorKind::DecodeError,
+                message: format!("Unknown encoding label: {}", label),
+            })
+        }
+    }
+}
+
+/// Decode raw bytes for `get_with_encoding`.
+#[cfg(feature = "encoding")]
+#[allow(clippy::needless_return)]
+fn decode_with(encoding: &'static encoding_rs::Encoding, content: &[u8]) -> Result<String, Error> {
+    let (decoded, _, had_errors) = encoding.decode(content);
+    if had_errors {
+        return Err(Error {
+            kind: ErrorKind::DecodeError,
+            message: format!("Value isn't valid {}.", encoding.name()),
+        });
+    }
+    return Ok(decoded.into_owned());
+}
+
+/// Read `Content-Length` header directly. (reqwest reports 0 for HEAD responses)
+#[allow(clippy::needless_return)]
+fn header_content_length(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    return headers
+        .get(reqwest::header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok();
+}
+
+/// Error for a response with a status that isn't expected (nor a 404 handled by the caller).
+#[allow(clippy::needless_return)]
+fn unexpected_status(status: reqwest::StatusCode) -> Error {
+    return Error {
+        kind: ErrorKind::HttpError,
+        message: format!("Database responded with unexpected status {}.", status),
+    };
+}
+
+/// Turn [`ErrorKind::ValueTooLargeError`] into [`None`], keeping other errors.
+#[allow(clippy::needless_return)]
+fn too_large_as_none(res: Result<String, Error>) -> Result<Option<String>, Error> {
+    match res {
+        Ok(value) => return Ok(Some(value)),
+        Err(e) => match e.kind {
+            ErrorKind::ValueTooLargeError => return Ok(None),
+            _ => return Err(e),
+        },
+    }
+}
+
+/// Map response's status of a ping into [`PingStatus`].
+#[allow(clippy::needless_return)]
+fn ping_status(status: reqwest::StatusCode) -> PingStatus {
+    if status.is_success() {
+        return PingStatus::Reachable;
+    }
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        return PingStatus::Unauthorized;
+    }
+    return PingStatus::Unreachable;
+}
+
+/// Deduplicate keys, keeping their first appearance's order.
+#[allow(clippy::needless_return)]
+fn unique_keys<T: AsRef<str>>(keys: &[T]) -> std::vec::Vec<&str> {
+    let mut seen = std::collections::HashSet::new();
+    return keys
+        .iter()
+        .map(|key| key.as_ref())
+        .filter(|key| seen.insert(*key))
+        .collect();
+}
+
+/// Turn [`ErrorKind::NoItemFoundError`] into [`None`], keeping other errors.
+#[allow(clippy::needless_return)]
+fn not_found_as_none(res: Result<String, Error>) -> Result<Option<String>, Error> {
+    match res {
+        Ok(value) => return Ok(Some(value)),
+        Err(e) => match e.kind {
+            ErrorKind::NoItemFoundError => return Ok(None),
+            _ => return Err(e),
+        },
+    }
+}
+
+/// Diff a `with_snapshot` map against what was loaded. [`None`] value means the key should be deleted.
+#[allow(clippy::needless_return)]
+fn snapshot_changes(
+    keys: &[&str],
+    original: &std::collections::HashMap<String, String>,
+    snapshot: &std::collections::HashMap<String, String>,
+) -> std::vec::Vec<(String, Option<String>)> {
+    let mut changes = std::vec::Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for key in keys {
+        if !seen.insert(*key) {
+            continue;
+        }
+        match (original.get(*key), snapshot.get(*key)) {
+            (Some(old), Some(new)) if old == new => {}
+            (_, Some(new)) => changes.push((key.to_string(), Some(new.clone()))),
+            (Some(_), None) => changes.push((key.to_string(), None)),
+            (None, None) => {}
+        }
+    }
+    return changes;
+}
+
+/// Parse a JSON object for `import_json`.
+#[cfg(feature = "serde")]
+#[allow(clippy::needless_return, clippy::unnecessary_unwrap)]
+fn parse_json_object(json: &str) -> Result<serde_json::Map<String, serde_json::Value>, Error> {
+    let res = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(json);
+    if res.is_err() {
+        return Err(Error {
+            kind: ErrorKind::JsonError,
+            message: res.unwrap_err().to_string(),
+        });
+    }
+    return Ok(res.unwrap());
+}
+
+/// Incremental parser of a JSON array for `get_json_array_stream`. Bytes are fed as they arrive and elements are taken out one by one.
+/// Each element's end is found by scanning the bytes once (across chunks), then the element is deserialized once,
+/// so a large element split over many chunks isn't parsed again for every chunk.
+#[cfg(feature = "serde")]
+struct JsonArrayParser {
+    buffer: std::vec::Vec<u8>,
+    position: usize,
+    state: JsonArrayState,
+    eof: bool,
+    /// How far the current element was scanned.
+    scan: usize,
+    /// Nesting depth of the scan within the current element.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+#[cfg(feature = "serde")]
+enum JsonArrayState {
+    /// Expecting `[`.
+    Start,
+    /// Just after `[`, expecting an element or `]`.
+    First,
+    /// Expecting an element.
+    Element,
+    /// Expecting `,` or `]`.
+    Separator,
+    Done,
+}
+
+#[cfg(feature = "serde")]
+enum JsonArrayItem<V> {
+    Item(Result<V, Error>),
+    NeedMore,
+    Done,
+}
+
+#[cfg(feature = "serde")]
+#[allow(clippy::needless_return, clippy::redundant_field_names)]
+impl JsonArrayParser {
+    fn new() -> Self {
+        return Self {
+            buffer: std::vec::Vec::new(),
+            position: 0,
+            state: JsonArrayState::Start,
+            eof: false,
+            scan: 0,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+        };
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        if self.position > 0 && self.position * 2 >= self.buffer.len() {
+            self.buffer.drain(..self.position);
+            self.scan -= self.position;
+            self.position = 0;
+        }
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Mark the end of the body.
+    fn finish(&mut self) {
+        self.eof = true;
+    }
+
+    fn fail<V>(&mut self, message: String) -> JsonArrayItem<V> {
+        self.state = JsonArrayState::Done;
+        return JsonArrayItem::Item(Err(Error {
+            kind: ErrorKind::JsonError,
+            message: message,
+        }));
+    }
+
+    /// Continue scanning the current element, returning where it ends (the `,` or `]` after it) once it's found.
+    fn element_end(&mut self) -> Option<usize> {
+        while self.scan < self.buffer.len() {
+            let byte = self.buffer[self.scan];
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                }
+            } else {
+                match byte {
+                    b'"' => self.in_string = true,
+                    b'[' | b'{' => self.depth += 1,
+                    b']' | b'}' if self.depth > 0 => self.depth -= 1,
+                    b',' | b']' if self.depth == 0 => return Some(self.scan),
+                    _ => {}
+                }
+            }
+            self.scan += 1;
+        }
+        return None;
+    }
+
+    fn next_item<V: serde::de::DeserializeOwned>(&mut self) -> JsonArrayItem<V> {
+        loop {
+            while self.position < self.buffer.len()
+                && self.buffer[self.position].is_ascii_whitespace()
+            {
+                self.position += 1;
+            }
+            if let JsonArrayState::Done = self.state {
+                return JsonArrayItem::Done;
+            }
+            if self.position == self.buffer.len() {
+                if self.eof {
+                    return self.fail("Unexpected end of JSON array.".to_string());
+                }
+                return JsonArrayItem::NeedMore;
+            }
+            let byte = self.buffer[self.position];
+            match self.state {
+                JsonArrayState::Start => {
+                    if byte != b'[' {
+                        return self.fail("Value isn't a JSON array.".to_string());
+                    }
+                    self.position += 1;
+                    self.scan = self.position;
+                    self.state = JsonArrayState::First;
+                    continue;
+                }
+                JsonArrayState::First if byte == b']' => {
+                    self.position += 1;
+                    self.state = JsonArrayState::Done;
+                    continue;
+                }
+                JsonArrayState::Separator => {
+                    self.position += 1;
+                    match byte {
+                        b',' => {
+                            self.scan = self.position;
+                            self.state = JsonArrayState::Element;
+                        }
+                        b']' => self.state = JsonArrayState::Done,
+                        _ => return self.fail("Expected `,` or `]` in JSON array.".to_string()),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            let end = match self.element_end() {
+                Some(end) => end,
+                None if self.eof => self.buffer.len(), // unterminated, parsing it reports what's wrong
+                None => return JsonArrayItem::NeedMore,
+            };
+            let res = serde_json::from_slice::<V>(&self.buffer[self.position..end]);
+            self.position = end;
+            self.state = JsonArrayState::Separator;
+            match res {
+                Ok(element) => return JsonArrayItem::Item(Ok(element)),
+                Err(e) => return self.fail(e.to_string()),
+            }
+        }
+    }
+}
+
+/// Run `set_json`'s validator then serialize the value.
+#[cfg(feature = "serde")]
+#[allow(clippy::needless_return, clippy::unnecessary_unwrap)]
+fn to_json_validated<V: serde::Serialize>(
+    value: &V,
+    validator: Option<JsonValidator<V>>,
+) -> Result<String, Error> {
+    if let Some(validator) = validator {
+        let res = validator(value);
+        if res.is_err() {
+            return Err(Error {
+                kind: ErrorKind::ValidationError,
+                message: res.unwrap_err(),
+            });
+        }
+    }
+    let res = serde_json::to_string(value);
+    if res.is_err() {
+        return Err(Error {
+            kind: ErrorKind::JsonError,
+            message: res.unwrap_err().to_string(),
+        });
+    }
+    return Ok(res.unwrap());
+}
+
+/// Wait for `duration` without blocking the executor, whichever runtime is driving it.
+async fn sleep(duration: std::time::Duration) {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        let _ = tx.send(());
+    });
+    let _ = rx.await;
+}
+
+/// Error for a write that didn't become visible in time.
+#[allow(clippy::needless_return)]
+fn visibility_timeout(timeout: std::time::Duration) -> Error {
+    return Error {
+        kind: ErrorKind::TimeoutError,
+        message: format!("Write didn't become visible within {:?}.", timeout),
+    };
+}
+
+/// Error for a key that still exists after being deleted.
+#[allow(clippy::needless_return)]
+fn still_exists(key: &str) -> Error {
+    return Error {
+        kind: ErrorKind::ConsistencyError,
+        message: format!("{} still exists after being deleted.", key),
+    };
+}
+
+/// Whether a listing response announces an empty listing, with a `204` status.
+/// An empty `200` (even with `Content-Length: 0`) doesn't count, as a hiccup looks exactly the same.
+#[allow(clippy::needless_return)]
+fn announces_empty(status: reqwest::StatusCode) -> bool {
+    return status == reqwest::StatusCode::NO_CONTENT;
+}
+
+/// Read `Last-Modified` and `ETag` headers of a value.
+#[allow(clippy::needless_return)]
+fn value_metadata(
+    headers: &reqwest::header::HeaderMap,
+) -> (Option<std::time::SystemTime>, Option<String>) {
+    let last_modified = headers
+        .get(reqwest::header::LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok());
+    let etag = headers
+        .get(reqwest::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    return (last_modified, etag);
+}
+
+/// Gzip compress then base64 encode a value for `set_compressed`.
+#[cfg(feature = "compression")]
+#[allow(clippy::needless_return, clippy::unnecessary_unwrap)]
+fn compress_value(value: &str) -> Result<String, Error> {
+    let mut encoder =
+        flate2::write::GzEncoder::new(std::vec::Vec::new(), flate2::Compression::default());
+    let res = std::io::Write::write_all(&mut encoder, value.as_bytes());
+    if res.is_err() {
+        return Err(Error {
+            kind: ErrorKind::InvalidValueError,
+            message: res.unwrap_err().to_string(),
+        });
+    }
+    let compressed = encoder.finish();
+    if compressed.is_err() {
+        return Err(Error {
+            kind: ErrorKind::InvalidValueError,
+            message: compressed.unwrap_err().to_string(),
+        });
+    }
+    return Ok(base64::Engine::encode(
+        &base64::engine::general_purpose::STANDARD,
+        compressed.unwrap(),
+    ));
+}
+
+/// Reject a plain write's value that would be mistaken for a reserved value format when read back. (See [`COMPRESSED_VALUE_PREFIX`])
+/// Only checked with [`Config::with_auto_decompress`], as nothing is decompressed without it.
+#[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+#[allow(clippy::needless_return)]
+fn reject_reserved_value(value: &str, config: &Config) -> Result<(), Error> {
+    #[cfg(feature = "compression")]
+    if config.auto_decompress && looks_compressed(value, config.max_value_bytes) {
+        return Err(Error {
+            kind: ErrorKind::ReservedValueError,
+            message: format!(
+                "Value looks like a compressed value (starts with {:?} and decompresses), store it with set_compressed instead.",
+                COMPRESSED_VALUE_PREFIX
+            ),
+        });
+    }
+    return Ok(());
+}
+
+/// Whether `value` would be decompressed (or fail to be read back) with [`Config::with_auto_decompress`].
+/// It's decompressed through a fixed buffer and thrown away, only checking it's UTF-8. Going over `max_value_bytes`
+/// (or [`MAX_CHECKED_DECOMPRESSED_BYTES`] without it) counts as compressed, so a small value can't make it decompress forever.
+#[cfg(feature = "compression")]
+#[allow(clippy::needless_return)]
+fn looks_compressed(value: &str, max_value_bytes: Option<usize>) -> bool {
+    if !value.starts_with(COMPRESSED_VALUE_PREFIX) {
+        return false;
+    }
+    let compressed = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, value);
+    if compressed.is_err() {
+        return false;
+    }
+    let compressed = compressed.unwrap();
+    let limit = max_value_bytes.map_or(MAX_CHECKED_DECOMPRESSED_BYTES, |max| {
+        max.min(MAX_CHECKED_DECOMPRESSED_BYTES)
+    });
+    let mut decoder = flate2::read::GzDecoder::new(compressed.as_slice());
+    let mut buffer = [0; 8192];
+    // an incomplete character at the end of the last chunk, finished by the next one
+    let mut pending: std::vec::Vec<u8> = std::vec::Vec::new();
+    let mut total = 0;
+    loop {
+        let read = match std::io::Read::read(&mut decoder, &mut buffer) {
+            Ok(0) => return pending.is_empty(),
+            Ok(read) => read,
+            Err(_) => return false,
+        };
+        total += read;
+        if total > limit {
+            return true;
+        }
+        pending.extend_from_slice(&buffer[..read]);
+        match std::str::from_utf8(&pending) {
+            Ok(_) => pending.clear(),
+            Err(e) => {
+                if e.error_len().is_some() {
+                    return false;
+                }
+                pending.drain(..e.valid_up_to());
+            }
+        }
+    }
+}
+
+/// Decompress a value written by `set_compressed`, stopping early if it goes over `max_value_bytes`.
+/// [`None`] if it isn't one (or isn't decompressable to UTF-8).
+#[cfg(feature = "compression")]
+#[allow(clippy::needless_return)]
+fn decompress_value(value: &str, max_value_bytes: Option<usize>) -> Result<Option<String>, Error> {
+    if !value.starts_with(COMPRESSED_VALUE_PREFIX) {
+        return Ok(None);
+    }
+    let compressed = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, value);
+    if compressed.is_err() {
+        return Ok(None);
+    }
+    let compressed = compressed.unwrap();
+    let limit = max_value_bytes.map_or(u64::MAX, |max| (max as u64).saturating_add(1));
+    let mut decoder =
+        std::io::Read::take(flate2::read::GzDecoder::new(compressed.as_slice()), limit);
+    let mut content = std::vec::Vec::new();
+    if std::io::Read::read_to_end(&mut decoder, &mut content).is_err() {
+        return Ok(None);
+    }
+    if let Some(max_value_bytes) = max_value_bytes {
+        if content.len() > max_value_bytes {
+            return Err(value_too_large(max_value_bytes));
+        }
+    }
+    return Ok(String::from_utf8(content).ok());
+}
+
+/// Turn a line of listing response into a key, without the line ending.
+#[allow(clippy::needless_return)]
+fn key_from_line(mut line: std::vec::Vec<u8>) -> Result<String, Error> {
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    match String::from_utf8(line) {
+        Ok(key) => return Ok(key),
+        Err(e) => {
+            return Err(Error {
+                kind: ErrorKind::DecodeError,
+                message: e.to_string(),
+            })
+        }
+    }
+}
+
+/// Keep keys starting with `prefix`, ignoring case.
+#[allow(clippy::needless_return)]
+fn filter_prefix_ci(keys: std::vec::Vec<String>, prefix: &str) -> std::vec::Vec<String> {
+    let prefix = prefix.to_lowercase();
+    return keys
+        .into_iter()
+        .filter(|key| key.to_lowercase().starts_with(prefix.as_str()))
+        .collect();
+}
+
+/// Parse a value stored at `key` with [`std::str::FromStr`].
+#[allow(clippy::needless_return)]
+fn parse_value<V: std::str::FromStr>(key: &str, value: &str) -> Result<V, Error>
+where
+    V::Err: std::fmt::Display,
+{
+    match value.parse::<V>() {
+        Ok(value) => return Ok(value),
+        Err(e) => {
+            return Err(Error {
+                kind: ErrorKind::ParseError,
+                message: format!("Unparsable value of {}: {}", key, e),
+            })
+        }
+    }
+}
+
+/// Deserialize a JSON value stored at `key`.
+#[cfg(feature = "serde")]
+#[allow(clippy::needless_return)]
+fn parse_json_value<V: serde::de::DeserializeOwned>(key: &str, value: &str) -> Result<V, Error> {
+    match serde_json::from_str::<V>(value) {
+        Ok(value) => return Ok(value),
+        Err(e) => {
+            return Err(Error {
+                kind: ErrorKind::JsonError,
+                message: format!("Malformed value of {}: {}", key, e),
+            })
+        }
+    }
+}
+
+/// Strings are stored as-is, anything else is stored as its JSON text.
+#[cfg(feature = "serde")]
+#[allow(clippy::needless_return)]
+fn json_value_to_string(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => return s,
+        other => return other.to_string(),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::needless_return, clippy::redundant_field_names)]
+mod tests {
+    use super::*;
+
+    /// State of a [`MockServer`], shared with its connection threads.
+    #[derive(Default)]
+    struct MockState {
+        /// Stored values.
+        kv: std::collections::BTreeMap<String, std::vec::Vec<u8>>,
+        /// Every request received, as `METHOD target`.
+        requests: std::vec::Vec<String>,
+        /// How long written values take to become visible, like a lagging replica.
+        write_lag: std::time::Duration,
+        /// Written values that aren't visible yet, with when they become visible.
+        pending: std::vec::Vec<(std::time::Instant, String, std::vec::Vec<u8>)>,
+        /// How long to wait before answering a `GET` (after its value was read).
+        get_delay: std::time::Duration,
+        /// Status to answer every request with (and an empty body) instead of handling it.
+        status: Option<u16>,
+        /// Close connections without answering, so requests fail to be sent.
+        hang_up: bool,
+        /// How many listings to answer with an empty body before listing normally.
+        empty_listings: usize,
+        /// How many `GET`s of stored keys to answer with an empty body before answering normally.
+        empty_gets: usize,
+        /// Status to answer specific requests (`METHOD target`) with, and an empty body.
+        overrides: std::collections::HashMap<String, u16>,
+        /// How long to wait between sending a response's headers and its body.
+        body_delay: std::time::Duration,
+        /// Requests being answered right now.
+        in_flight: usize,
+        /// Most requests that were being answered at the same time.
+        max_in_flight: usize,
+        /// Gates holding back the responses to requests starting with a prefix (`METHOD target`), see [`MockServer::hold`].
+        holds: std::vec::Vec<(String, std::sync::Arc<MockGate>)>,
+    }
+
+    /// Holds back responses until the test releases them, in the order their requests arrived.
+    #[derive(Default)]
+    struct MockGate {
+        /// How many requests arrived, and how many of them were released.
+        counts: std::sync::Mutex<(usize, usize)>,
+        changed: std::sync::Condvar,
+    }
+
+    impl MockGate {
+        /// Wait until this request is released.
+        fn pass(&self) {
+            let mut counts = self.counts.lock().unwrap();
+            let ticket = counts.0;
+            counts.0 += 1;
+            self.changed.notify_all();
+            while ticket >= counts.1 {
+                counts = self.changed.wait(counts).unwrap();
+            }
+        }
+
+        /// Wait until `n` requests arrived in total. (Panics if they don't arrive in time)
+        fn wait_arrivals(&self, n: usize) {
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+            let mut counts = self.counts.lock().unwrap();
+            while counts.0 < n {
+                let now = std::time::Instant::now();
+                assert!(
+                    now < deadline,
+                    "only {} of {} requests arrived",
+                    counts.0,
+                    n
+                );
+                counts = self.changed.wait_timeout(counts, deadline - now).unwrap().0;
+            }
+        }
+
+        /// Release the next `n` requests, including ones that haven't arrived yet.
+        fn release(&self, n: usize) {
+            let mut counts = self.counts.lock().unwrap();
+            counts.1 = counts.1.saturating_add(n);
+            self.changed.notify_all();
+        }
+
+        /// Release every request, from now on.
+        fn release_all(&self) {
+            self.release(usize::MAX);
+        }
+    }
+
+    /// Response of a [`MockServer`].
+    struct MockResponse {
+        status: u16,
+        body: std::vec::Vec<u8>,
+    }
+
+    impl MockResponse {
+        fn new(status: u16, body: impl Into<std::vec::Vec<u8>>) -> MockResponse {
+            return MockResponse {
+                status: status,
+                body: body.into(),
+            };
+        }
+    }
+
+    /// Minimal in-memory Replit database server on a local port, answering one request per connection.
+    struct MockServer {
+        url: String,
+        state: std::sync::Arc<std::sync::Mutex<MockState>>,
+    }
+
+    impl MockServer {
+        fn start() -> MockServer {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let state = std::sync::Arc::new(std::sync::Mutex::new(MockState::default()));
+            let shared = state.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let state = shared.clone();
+                    std::thread::spawn(move || mock_connection(stream, &state));
+                }
+            });
+            return MockServer {
+                url: url,
+                state: state,
+            };
+        }
+
+        fn config(&self) -> Config {
+            return Config::new_custom_url(&self.url);
+        }
+
+        fn database(&self) -> Database {
+            return Database::new(self.config());
+        }
+
+        fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+            return self.state.lock().unwrap();
+        }
+
+        /// Store a value directly, without going through the client.
+        fn insert(&self, key: &str, value: impl Into<std::vec::Vec<u8>>) {
+            self.state().kv.insert(key.to_string(), value.into());
+        }
+
+        /// Stored value of a key, read directly.
+        fn value(&self, key: &str) -> Option<String> {
+            return self
+                .state()
+                .kv
+                .get(key)
+                .map(|value| String::from_utf8_lossy(value).into_owned());
+        }
+
+        /// Every request received so far, as `METHOD target`.
+        fn requests(&self) -> std::vec::Vec<String> {
+            return self.state().requests.clone();
+        }
+
+        /// How many requests were exactly `request`. (`METHOD target`)
+        fn count(&self, request: &str) -> usize {
+            return self
+                .state()
+                .requests
+                .iter()
+                .filter(|r| r.as_str() == request)
+                .count();
+        }
+
+        /// Stored keys, read directly.
+        fn keys(&self) -> std::vec::Vec<String> {
+            return self.state().kv.keys().cloned().collect();
+        }
+
+        /// Hold back the responses to requests starting with `prefix` (`METHOD target`) until they're released through the returned gate.
+        /// They're held after being handled, so they count as in flight and see the values from when they arrived.
+        fn hold(&self, prefix: &str) -> std::sync::Arc<MockGate> {
+            let gate = std::sync::Arc::new(MockGate::default());
+            self.state().holds.push((prefix.to_string(), gate.clone()));
+            return gate;
+        }
+    }
+
+    /// Wait until `done` holds. (Panics if it doesn't in time)
+    #[cfg(feature = "cache")]
+    fn wait_until(mut done: impl FnMut() -> bool) {
+        let start = std::time::Instant::now();
+        while !done() {
+            assert!(start.elapsed() < std::time::Duration::from_secs(10));
+            std::thread::yield_now();
+        }
+    }
+
+    /// Kind of the error `res` failed with. (Panics if it succeeded)
+    fn error_kind<T>(res: Result<T, Error>) -> ErrorKind {
+        match res {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => return e.kind,
+        }
+    }
+
+    /// Read a request from `stream`, answer it and close the connection.
+    fn mock_connection(stream: std::net::TcpStream, state: &std::sync::Mutex<MockState>) {
+        let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        if std::io::BufRead::read_line(&mut reader, &mut line).is_err() {
+            return;
+        }
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or("").to_string();
+        let target = parts.next().unwrap_or("").to_string();
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            if std::io::BufRead::read_line(&mut reader, &mut header).unwrap_or(0) == 0 {
+                break;
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        if std::io::Read::read_exact(&mut reader, &mut body).is_err() {
+            return;
+        }
+        if state.lock().unwrap().hang_up {
+            return;
+        }
+        let body_delay = {
+            let mut state = state.lock().unwrap();
+            state.in_flight += 1;
+            state.max_in_flight = state.max_in_flight.max(state.in_flight);
+            state.body_delay
+        };
+        let response = mock_respond(state, &method, &target, &body);
+        let request = format!("{} {}", method, target);
+        let gate = state
+            .lock()
+            .unwrap()
+            .holds
+            .iter()
+            .find(|(prefix, _)| request.starts_with(prefix.as_str()))
+            .map(|(_, gate)| gate.clone());
+        if let Some(gate) = gate {
+            gate.pass();
+        }
+        if method == "GET" {
+            std::thread::sleep(state.lock().unwrap().get_delay);
+        }
+        let head = format!(
+            "HTTP/1.1 {} Mock\r\ncontent-length: {}\r\nlast-modified: Wed, 21 Oct 2015 07:28:00 GMT\r\netag: \"mock\"\r\nconnection: close\r\n\r\n",
+            response.status,
+            response.body.len()
+        );
+        let mut stream = stream;
+        let _ = std::io::Write::write_all(&mut stream, head.as_bytes());
+        let _ = std::io::Write::flush(&mut stream);
+        std::thread::sleep(body_delay);
+        // done before the body goes out, as the client can send its next request as soon as it's read
+        state.lock().unwrap().in_flight -= 1;
+        if method != "HEAD" {
+            let _ = std::io::Write::write_all(&mut stream, &response.body);
+        }
+    }
+
+    /// Answer a request like Replit's database does.
+    fn mock_respond(
+        state: &std::sync::Mutex<MockState>,
+        method: &str,
+        target: &str,
+        body: &[u8],
+    ) -> MockResponse {
+        let mut state = state.lock().unwrap();
+        state.requests.push(format!("{} {}", method, target));
+        let now = std::time::Instant::now();
+        let (visible, pending) = std::mem::take(&mut state.pending)
+            .into_iter()
+            .partition(|(at, _, _)| *at <= now);
+        state.pending = pending;
+        for (_, key, value) in visible {
+            state.kv.insert(key, value);
+        }
+        if let Some(status) = state.status {
+            return MockResponse::new(status, "");
+        }
+        if let Some(status) = state.overrides.get(&format!("{} {}", method, target)) {
+            return MockResponse::new(*status, "");
+        }
+        if method == "POST" {
+            for pair in String::from_utf8_lossy(body).split('&') {
+                if let Some((key, value)) = pair.split_once('=') {
+                    let key = urlencoding::decode(key).unwrap().into_owned();
+                    let value = urlencoding::decode_binary(value.as_bytes()).into_owned();
+                    if state.write_lag.is_zero() {
+                        state.kv.insert(key, value);
+                    } else {
+                        let at = now + state.write_lag;
+                        state.pending.push((at, key, value));
+                    }
+                }
+            }
+            return MockResponse::new(200, "");
+        }
+        if let Some(prefix) = target.strip_prefix("/?prefix=") {
+            if state.empty_listings > 0 {
+                state.empty_listings -= 1;
+                return MockResponse::new(200, "");
+            }
+            let prefix = urlencoding::decode(prefix).unwrap().into_owned();
+            let keys: std::vec::Vec<&str> = state
+                .kv
+                .keys()
+                .filter(|key| key.starts_with(&prefix))
+                .map(|key| key.as_str())
+                .collect();
+            return MockResponse::new(200, keys.join("\n"));
+        }
+        let key = urlencoding::decode(target.trim_start_matches('/'))
+            .unwrap()
+            .into_owned();
+        match method {
+            "GET" if state.empty_gets > 0 && state.kv.contains_key(&key) => {
+                state.empty_gets -= 1;
+                return MockResponse::new(200, "");
+            }
+            "GET" | "HEAD" => match state.kv.get(&key) {
+                Some(value) => return MockResponse::new(200, value.clone()),
+                None => return MockResponse::new(404, ""),
+            },
+            "DELETE" => match state.kv.remove(&key) {
+                Some(_) => return MockResponse::new(204, ""),
+                None => return MockResponse::new(404, ""),
+            },
+            _ => return MockResponse::new(405, ""),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn export_json_round_trips_through_import_json() {
+        let source = MockServer::start();
+        source.insert("user/1", "alice");
+        source.insert("user/2", "{\"admin\":true}");
+        source.insert("other", "skipped");
+        let json = Synchronous::export_json(&source.database(), Some("user/")).unwrap();
+
+        let dest = MockServer::start();
+        dest.insert("user/1", "old");
+        let written = Synchronous::import_json(&dest.database(), &json, false).unwrap();
+        assert_eq!(written, 1);
+        assert_eq!(dest.value("user/1").as_deref(), Some("old"));
+        let written = Synchronous::import_json(&dest.database(), &json, true).unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(dest.keys(), vec!["user/1", "user/2"]);
+        assert_eq!(dest.value("user/1").as_deref(), Some("alice"));
+        assert_eq!(dest.value("user/2").as_deref(), Some("{\"admin\":true}"));
+    }
+
+    #[test]
+    fn over_length_keys_are_rejected_before_sending() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_max_key_length(8));
+        let key = "k".repeat(9);
+        server.insert(&key, "stored");
+        let res = Synchronous::set(&db, &key, "value");
+        assert!(matches!(error_kind(res), ErrorKind::InvalidKeyError));
+        assert!(matches!(
+            error_kind(Synchronous::get(&db, &key)),
+            ErrorKind::InvalidKeyError
+        ));
+        let res = Synchronous::delete(&db, &key);
+        assert!(matches!(error_kind(res), ErrorKind::InvalidKeyError));
+        let default_limit = "k".repeat(REPLIT_MAX_KEY_LENGTH + 1);
+        let res = Synchronous::get(&server.database(), &default_limit);
+        assert!(matches!(error_kind(res), ErrorKind::InvalidKeyError));
+        assert!(server.requests().is_empty());
+        assert_eq!(server.value(&key).as_deref(), Some("stored"));
+        Synchronous::set(&db, "k".repeat(8), "value").unwrap();
+        assert_eq!(server.requests().len(), 1);
+        assert_eq!(server.keys(), vec!["k".repeat(8), key]);
+    }
+
+    #[test]
+    fn get_with_fallback_prefers_primary_key() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("old", "from fallback");
+        let value = Synchronous::get_with_fallback(&db, "new", "old").unwrap();
+        assert_eq!(value.as_deref(), Some("from fallback"));
+        server.insert("new", "from primary");
+        let value = Synchronous::get_with_fallback(&db, "new", "old").unwrap();
+        assert_eq!(value.as_deref(), Some("from primary"));
+        let value = Synchronous::get_with_fallback(&db, "missing", "gone").unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[test]
+    fn control_chars_in_values_are_rejected_when_enabled() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_reject_control_chars_in_values(true));
+        let res = Synchronous::set(&db, "key", "line\nbreak");
+        assert!(matches!(error_kind(res), ErrorKind::InvalidValueError));
+        let res = Synchronous::set(&db, "key", "bell\u{7}");
+        assert!(matches!(error_kind(res), ErrorKind::InvalidValueError));
+        assert!(server.requests().is_empty());
+        Synchronous::set(&db, "key", "tab\tis fine").unwrap();
+        assert_eq!(server.value("key").as_deref(), Some("tab\tis fine"));
+        Synchronous::set(&server.database(), "key", "line\nbreak").unwrap();
+        assert_eq!(server.value("key").as_deref(), Some("line\nbreak"));
+    }
+
+    #[test]
+    fn bench_reports_operations_and_cleans_up() {
+        let server = MockServer::start();
+        let result = Synchronous::bench(&server.database(), 10, 2).unwrap();
+        assert_eq!(result.ops, 10);
+        assert_eq!(result.errors, 0);
+        assert_eq!(result.error_rate, 0.0);
+        assert!(result.ops_per_sec > 0.0);
+        assert!(server.keys().is_empty());
+        assert!(server.requests().iter().any(|r| r.starts_with("POST")));
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn get_swr_serves_cached_value_then_refreshes() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("key", "v1");
+        let max_stale = std::time::Duration::from_secs(60);
+        assert_eq!(Synchronous::get_swr(&db, "key", max_stale).unwrap(), "v1");
+        server.insert("key", "v2");
+        let gate = server.hold("GET /key");
+        assert_eq!(Synchronous::get_swr(&db, "key", max_stale).unwrap(), "v1");
+        gate.wait_arrivals(1);
+        // the refresh is still running, so this one doesn't start another
+        assert_eq!(Synchronous::get_swr(&db, "key", max_stale).unwrap(), "v1");
+        gate.release_all();
+        wait_until(|| db.cache.lock().unwrap().fetching.is_empty());
+        assert_eq!(server.count("GET /key"), 2);
+        assert_eq!(Synchronous::get_swr(&db, "key", max_stale).unwrap(), "v2");
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn get_swr_refresh_racing_a_set_is_not_cached() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("key", "v1");
+        let max_stale = std::time::Duration::from_secs(60);
+        assert_eq!(Synchronous::get_swr(&db, "key", max_stale).unwrap(), "v1");
+        server.insert("key", "v2");
+        let gate = server.hold("GET /key");
+        // starts a refresh which reads v2 now but only answers after the set below
+        assert_eq!(Synchronous::get_swr(&db, "key", max_stale).unwrap(), "v1");
+        gate.wait_arrivals(1);
+        Synchronous::set(&db, "key", "v3").unwrap();
+        gate.release_all();
+        wait_until(|| db.cache.lock().unwrap().fetching.is_empty());
+        assert_eq!(Synchronous::get_swr(&db, "key", max_stale).unwrap(), "v3");
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn get_shared_is_cached_when_another_key_is_written_meanwhile() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("a", "1");
+        let gate = server.hold("GET /a");
+        let fetch = {
+            let db = db.clone();
+            std::thread::spawn(move || Synchronous::get_shared(&db, "a").unwrap())
+        };
+        gate.wait_arrivals(1);
+        Synchronous::set(&db, "b", "2").unwrap();
+        gate.release_all();
+        assert_eq!(&*fetch.join().unwrap(), "1");
+        assert_eq!(&*Synchronous::get_shared(&db, "a").unwrap(), "1");
+        assert_eq!(server.count("GET /a"), 1);
+    }
+
+    #[test]
+    fn with_snapshot_writes_back_changes() {
+        let server = MockServer::start();
+        server.insert("a", "1");
+        server.insert("gone", "x");
+        let keys = ["a", "b", "gone"];
+        let res = Synchronous::with_snapshot(&server.database(), &keys, |snapshot| {
+            assert_eq!(snapshot.len(), 2);
+            snapshot.insert("a".to_string(), "2".to_string());
+            snapshot.insert("b".to_string(), "new".to_string());
+            snapshot.insert("ignored".to_string(), "outside keys".to_string());
+            snapshot.remove("gone");
+            return "done";
+        });
+        assert_eq!(res.unwrap(), "done");
+        assert_eq!(server.keys(), vec!["a", "b"]);
+        assert_eq!(server.value("a").as_deref(), Some("2"));
+        assert_eq!(server.value("b").as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn with_snapshot_rolls_back_when_writing_fails() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_reject_control_chars_in_values(true));
+        server.insert("a", "1");
+        let res = Synchronous::with_snapshot(&db, &["a", "b"], |snapshot| {
+            snapshot.insert("a".to_string(), "2".to_string());
+            snapshot.insert("b".to_string(), "bad\n".to_string());
+        });
+        assert!(matches!(error_kind(res), ErrorKind::InvalidValueError));
+        assert_eq!(server.keys(), vec!["a"]);
+        assert_eq!(server.value("a").as_deref(), Some("1"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn list_with_values_json_deserializes_values() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("user/1", "[\"alice\",30]");
+        server.insert("user/2", "[\"bob\",41]");
+        server.insert("other", "[\"skipped\",0]");
+        /// Name and age.
+        type User = (String, u32);
+        let users: std::vec::Vec<(String, User)> =
+            Synchronous::list_with_values_json(&db, Some("user/"), false).unwrap();
+        assert_eq!(
+            users,
+            vec![
+                ("user/1".to_string(), ("alice".to_string(), 30)),
+                ("user/2".to_string(), ("bob".to_string(), 41)),
+            ]
+        );
+        server.insert("user/3", "not json");
+        let users: std::vec::Vec<(String, User)> =
+            Synchronous::list_with_values_json(&db, Some("user/"), true).unwrap();
+        assert_eq!(users.len(), 2);
+        let res: Result<std::vec::Vec<(String, User)>, Error> =
+            Synchronous::list_with_values_json(&db, Some("user/"), false);
+        assert!(matches!(error_kind(res), ErrorKind::JsonError));
+    }
+
+    #[test]
+    fn ping_reports_reachability() {
+        let server = MockServer::start();
+        let db = server.database();
+        assert!(matches!(Synchronous::ping(&db), PingStatus::Reachable));
+        server.state().status = Some(401);
+        assert!(matches!(Synchronous::ping(&db), PingStatus::Unauthorized));
+        server.state().status = Some(500);
+        assert!(matches!(Synchronous::ping(&db), PingStatus::Unreachable));
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let db = Database::new(Config::new_custom_url(&url));
+        assert!(matches!(Synchronous::ping(&db), PingStatus::Unreachable));
+    }
+
+    #[test]
+    fn clone_namespace_copies_prefixed_keys() {
+        let server = MockServer::start();
+        server.insert("test/a", "1");
+        server.insert("test/nested/b", "2");
+        server.insert("testing", "not under the prefix");
+        let copied = Synchronous::clone_namespace(&server.database(), "test/", "test2/").unwrap();
+        assert_eq!(copied, 2);
+        assert_eq!(server.value("test2/a").as_deref(), Some("1"));
+        assert_eq!(server.value("test2/nested/b").as_deref(), Some("2"));
+        assert_eq!(server.value("test/a").as_deref(), Some("1"));
+        assert_eq!(server.keys().len(), 5);
+    }
+
+    #[test]
+    fn get_stops_at_max_value_bytes() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_max_value_bytes(Some(16)));
+        server.insert("small", "x".repeat(16));
+        server.insert("large", "x".repeat(1024 * 1024));
+        assert_eq!(Synchronous::get(&db, "small").unwrap(), "x".repeat(16));
+        let res = Synchronous::get(&db, "large");
+        assert!(matches!(error_kind(res), ErrorKind::ValueTooLargeError));
+        assert!(Synchronous::exists(&db, "large").unwrap());
+        assert!(!Synchronous::exists(&db, "missing").unwrap());
+        assert_eq!(server.count("GET /large"), 1);
+        assert_eq!(server.count("HEAD /large"), 1);
+    }
+
+    #[test]
+    fn list_expect_nonempty_retries_empty_listings() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("key", "value");
+        server.state().empty_listings = 2;
+        let keys = Synchronous::list_expect_nonempty(&db, None::<&str>, 2).unwrap();
+        assert_eq!(keys, vec!["key"]);
+        assert_eq!(server.requests().len(), 3);
+        server.state().empty_listings = 2;
+        let keys = Synchronous::list_expect_nonempty(&db, None::<&str>, 1).unwrap();
+        assert!(keys.is_empty());
+    }
+
+    #[test]
+    fn set_rejects_values_over_max_value_bytes() {
+        let server = MockServer::start();
+        let db = Database::new(
+            server
+                .config()
+                .with_max_value_bytes(Some(REPLIT_MAX_VALUE_BYTES)),
+        );
+        let res = Synchronous::set(&db, "key", "x".repeat(REPLIT_MAX_VALUE_BYTES + 1));
+        assert!(matches!(error_kind(res), ErrorKind::ValueTooLargeError));
+        assert!(server.requests().is_empty());
+        Synchronous::set(&db, "key", "x".repeat(1024)).unwrap();
+        assert_eq!(server.value("key").map(|v| v.len()), Some(1024));
+    }
+
+    #[test]
+    fn get_many_fetches_repeated_keys_once() {
+        let server = MockServer::start();
+        server.insert("a", "1");
+        let values =
+            Synchronous::get_many(&server.database(), &["a", "missing", "a", "a"]).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                Some("1".to_string()),
+                None,
+                Some("1".to_string()),
+                Some("1".to_string())
+            ]
+        );
+        assert_eq!(server.count("GET /a"), 1);
+        assert_eq!(server.count("GET /missing"), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn set_json_runs_validator_before_writing() {
+        let server = MockServer::start();
+        let db = server.database();
+        fn positive(value: &i64) -> Result<(), String> {
+            if *value > 0 {
+                return Ok(());
+            }
+            return Err("must be positive".to_string());
+        }
+        let res = Synchronous::set_json(&db, "count", &-1, Some(positive));
+        let e = res.unwrap_err();
+        assert!(matches!(e.kind, ErrorKind::ValidationError));
+        assert!(e.message.contains("must be positive"));
+        assert!(server.requests().is_empty());
+        Synchronous::set_json(&db, "count", &3, Some(positive)).unwrap();
+        assert_eq!(Synchronous::get_json::<i64>(&db, "count").unwrap(), 3);
+    }
+
+    #[test]
+    fn list_with_small_values_only_fetches_small_values() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_trim_trailing_newline(true));
+        server.insert("a", "small\n");
+        server.insert("b", "x".repeat(100));
+        server.insert("c", "deleted after listing");
+        server.state().overrides.insert("HEAD /c".to_string(), 404);
+        let pairs = Synchronous::list_with_small_values(&db, None::<&str>, 10).unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                ("a".to_string(), Some("small".to_string())),
+                ("b".to_string(), None)
+            ]
+        );
+        assert_eq!(server.count("GET /a"), 1);
+        assert_eq!(server.count("GET /b"), 0);
+        server.state().overrides.insert("HEAD /c".to_string(), 500);
+        let res = Synchronous::list_with_small_values(&db, None::<&str>, 10);
+        assert!(matches!(error_kind(res), ErrorKind::HttpError));
+    }
+
+    #[tokio::test]
+    async fn set_with_receipt_describes_the_write() {
+        let server = MockServer::start();
+        let before = std::time::SystemTime::now();
+        let receipt = Asynchronous::set_with_receipt(&server.database(), "key", "café")
+            .await
+            .unwrap();
+        assert_eq!(receipt.key, "key");
+        assert_eq!(receipt.bytes, 5);
+        assert!(receipt.at >= before && receipt.at <= std::time::SystemTime::now());
+        assert_eq!(server.value("key").as_deref(), Some("café"));
+    }
+
+    #[test]
+    fn require_prefix_rejects_empty_prefixes() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_require_prefix(true));
+        server.insert("user/1", "alice");
+        let res = Synchronous::list(&db, None::<&str>);
+        assert!(matches!(error_kind(res), ErrorKind::InvalidPrefixError));
+        let res = Synchronous::list(&db, Some(" "));
+        assert!(matches!(error_kind(res), ErrorKind::InvalidPrefixError));
+        let res = Synchronous::delete_prefix(&db, "");
+        assert!(matches!(error_kind(res), ErrorKind::InvalidPrefixError));
+        assert!(server.requests().is_empty());
+        assert_eq!(server.keys(), vec!["user/1"]);
+        assert_eq!(
+            Synchronous::list(&db, Some("user/")).unwrap(),
+            vec!["user/1"]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn get_json_array_stream_yields_every_element() {
+        let server = MockServer::start();
+        let db = server.database();
+        let array: std::vec::Vec<String> = (0..100_000).map(|i| i.to_string()).collect();
+        server.insert("numbers", format!("[{}]", array.join(",")));
+        let mut count = 0;
+        for (i, element) in Synchronous::get_json_array_stream::<u64>(&db, "numbers").enumerate() {
+            assert_eq!(element.unwrap(), i as u64);
+            count += 1;
+        }
+        assert_eq!(count, 100_000);
+        server.insert("broken", "[1, 2, oops, 4]");
+        let elements: std::vec::Vec<Result<u64, Error>> =
+            Synchronous::get_json_array_stream(&db, "broken").collect();
+        assert_eq!(elements.len(), 3);
+        assert!(matches!(
+            error_kind(elements.into_iter().nth(2).unwrap()),
+            ErrorKind::JsonError
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn get_json_array_stream_reads_a_large_element_over_many_chunks() {
+        let server = MockServer::start();
+        let db = server.database();
+        let array = vec![
+            serde_json::json!({"nested": [[1, 2], {"a": "]"}]}),
+            serde_json::json!(["],{\"\\", "x".repeat(4 * 1024 * 1024)]),
+            serde_json::json!(3),
+        ];
+        server.insert("large", serde_json::to_string(&array).unwrap());
+        let elements: std::vec::Vec<serde_json::Value> =
+            Synchronous::get_json_array_stream(&db, "large")
+                .map(|element| element.unwrap())
+                .collect();
+        assert_eq!(elements, array);
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn async_get_json_array_stream_yields_every_element() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("numbers", "[1, 2, 3]");
+        let stream = Asynchronous::get_json_array_stream::<_, u64>(&db, "numbers");
+        let elements: std::vec::Vec<u64> = futures::StreamExt::collect::<std::vec::Vec<_>>(stream)
+            .await
+            .into_iter()
+            .map(|element| element.unwrap())
+            .collect();
+        assert_eq!(elements, vec![1, 2, 3]);
+        let res = Asynchronous::get_json_array_stream::<_, u64>(&db, "missing");
+        let elements = futures::StreamExt::collect::<std::vec::Vec<_>>(res).await;
+        assert!(matches!(
+            error_kind(elements.into_iter().next().unwrap()),
+            ErrorKind::NoItemFoundError
+        ));
+    }
+
+    #[test]
+    fn copy_all_to_copies_into_another_database() {
+        let source = MockServer::start();
+        let dest = MockServer::start();
+        source.insert("app/a", "1");
+        source.insert("app/b", "2");
+        source.insert("other", "3");
+        dest.insert("app/a", "kept");
+        let copied =
+            Synchronous::copy_all_to(&source.database(), &dest.database(), Some("app/"), false)
+                .unwrap();
+        assert_eq!(copied, 1);
+        assert_eq!(dest.value("app/a").as_deref(), Some("kept"));
+        assert_eq!(dest.value("app/b").as_deref(), Some("2"));
+        let copied =
+            Synchronous::copy_all_to(&source.database(), &dest.database(), None::<&str>, true)
+                .unwrap();
+        assert_eq!(copied, 3);
+        assert_eq!(dest.value("app/a").as_deref(), Some("1"));
+        assert_eq!(dest.keys(), source.keys());
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn get_with_encoding_decodes_raw_bytes() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("latin1", vec![0x63, 0x61, 0x66, 0xE9]);
+        assert_eq!(
+            Synchronous::get_with_encoding(&db, "latin1", "latin1").unwrap(),
+            "café"
+        );
+        let res = Synchronous::get_with_encoding(&db, "latin1", "utf-8");
+        assert!(matches!(error_kind(res), ErrorKind::DecodeError));
+        let res = Synchronous::get_with_encoding(&db, "latin1", "not an encoding");
+        assert!(matches!(error_kind(res), ErrorKind::DecodeError));
+    }
+
+    #[test]
+    fn list_tree_nests_keys_by_delimiter() {
+        let server = MockServer::start();
+        server.insert("users/1", "alice");
+        server.insert("users/1/name", "Alice");
+        server.insert("users/2/name", "Bob");
+        server.insert("other", "skipped");
+        let tree = Synchronous::list_tree(&server.database(), Some("users/"), '/').unwrap();
+        assert_eq!(tree.key, None);
+        assert_eq!(
+            tree.children.keys().collect::<std::vec::Vec<_>>(),
+            vec!["1", "2"]
+        );
+        let first = &tree.children["1"];
+        assert_eq!(first.key.as_deref(), Some("users/1"));
+        assert_eq!(first.children["name"].key.as_deref(), Some("users/1/name"));
+        let second = &tree.children["2"];
+        assert_eq!(second.key, None);
+        assert_eq!(second.children["name"].key.as_deref(), Some("users/2/name"));
+        assert!(second.children["name"].children.is_empty());
+    }
+
+    #[test]
+    fn operation_hooks_see_every_request() {
+        let server = MockServer::start();
+        let events = std::sync::Arc::new(std::sync::Mutex::new(std::vec::Vec::new()));
+        let (before, after) = (events.clone(), events.clone());
+        let db = server.database().with_operation_hook(
+            move |operation, key| {
+                before
+                    .lock()
+                    .unwrap()
+                    .push(format!("before {:?} {}", operation, key));
+            },
+            move |operation, key, status| {
+                after
+                    .lock()
+                    .unwrap()
+                    .push(format!("after {:?} {} {:?}", operation, key, status));
+            },
+        );
+        Synchronous::set(&db, "key", "value").unwrap();
+        Synchronous::get(&db, "key").unwrap();
+        Synchronous::list(&db, Some("k")).unwrap();
+        Synchronous::delete(&db, "key").unwrap();
+        let res = Synchronous::get(&db, "key");
+        assert!(matches!(error_kind(res), ErrorKind::NoItemFoundError));
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                "before Set key",
+                "after Set key Some(200)",
+                "before Get key",
+                "after Get key Some(200)",
+                "before List k",
+                "after List k Some(200)",
+                "before Delete key",
+                "after Delete key Some(204)",
+                "before Get key",
+                "after Get key Some(404)",
+            ]
+        );
+        assert!(!events.iter().any(|event| event.contains(&server.url)));
+    }
+
+    #[test]
+    fn operation_hooks_see_requests_refused_by_the_circuit_breaker() {
+        let server = MockServer::start();
+        let statuses = std::sync::Arc::new(std::sync::Mutex::new(std::vec::Vec::new()));
+        let after = statuses.clone();
+        let cooldown = std::time::Duration::from_secs(60);
+        let db = Database::new(server.config().with_circuit_breaker(Some((1, cooldown))))
+            .with_operation_hook(
+                |_, _| {},
+                move |_, _, status| after.lock().unwrap().push(status),
+            );
+        server.state().status = Some(500);
+        assert!(Synchronous::get(&db, "key").is_err());
+        let res = Synchronous::get(&db, "key");
+        assert!(matches!(error_kind(res), ErrorKind::CircuitOpenError));
+        assert_eq!(server.count("GET /key"), 1);
+        assert_eq!(*statuses.lock().unwrap(), vec![Some(500), None]);
+    }
+
+    #[test]
+    fn set_display_stores_formatted_values() {
+        let server = MockServer::start();
+        let db = server.database();
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+        impl std::fmt::Display for Point {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                return write!(f, "({}, {})", self.x, self.y);
+            }
+        }
+        Synchronous::set_display(&db, "count", -42i64).unwrap();
+        Synchronous::set_display(&db, "point", Point { x: 1, y: 2 }).unwrap();
+        assert_eq!(server.value("count").as_deref(), Some("-42"));
+        assert_eq!(server.value("point").as_deref(), Some("(1, 2)"));
+    }
+
+    #[test]
+    fn get_parsed_parses_values() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("count", "42");
+        server.insert("ratio", "0.5");
+        server.insert("broken", "forty-two");
+        assert_eq!(Synchronous::get_parsed::<i64>(&db, "count").unwrap(), 42);
+        assert_eq!(Synchronous::get_parsed::<f64>(&db, "ratio").unwrap(), 0.5);
+        let res = Synchronous::get_parsed::<i64>(&db, "broken");
+        assert!(matches!(error_kind(res), ErrorKind::ParseError));
+        let res = Synchronous::get_parsed::<i64>(&db, "missing");
+        assert!(matches!(error_kind(res), ErrorKind::NoItemFoundError));
+    }
+
+    #[test]
+    fn list_prefix_ci_ignores_case() {
+        let server = MockServer::start();
+        server.insert("User1", "a");
+        server.insert("USER2", "b");
+        server.insert("user3", "c");
+        server.insert("admin", "d");
+        let keys = Synchronous::list_prefix_ci(&server.database(), "user").unwrap();
+        assert_eq!(keys, vec!["USER2", "User1", "user3"]);
+    }
+
+    #[test]
+    fn get_with_metadata_parses_headers() {
+        let server = MockServer::start();
+        server.insert("key", "value");
+        let value = Synchronous::get_with_metadata(&server.database(), "key").unwrap();
+        assert_eq!(value.value, "value");
+        assert_eq!(value.etag.as_deref(), Some("\"mock\""));
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1445412480);
+        assert_eq!(value.last_modified, Some(modified));
+    }
+
+    #[test]
+    fn delete_verified_checks_the_key_is_gone() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("key", "value");
+        Synchronous::delete_verified(&db, "key", 0).unwrap();
+        assert!(server.keys().is_empty());
+        // acknowledged but never applied, like a lagging replica
+        server.insert("stuck", "value");
+        server
+            .state()
+            .overrides
+            .insert("DELETE /stuck".to_string(), 204);
+        let res = Synchronous::delete_verified(&db, "stuck", 1);
+        assert!(matches!(error_kind(res), ErrorKind::ConsistencyError));
+        assert_eq!(server.count("HEAD /stuck"), 2);
+    }
+
+    #[cfg(feature = "blocking-adapter")]
+    #[tokio::test]
+    async fn blocking_adapter_does_not_stall_the_runtime() {
+        let server = MockServer::start();
+        let adapter = server.database().blocking_adapter();
+        adapter.set("key", "value").await.unwrap();
+        let ticks = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = ticks.clone();
+        let ticker = tokio::spawn(async move {
+            loop {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        });
+        server.state().get_delay = std::time::Duration::from_millis(300);
+        // single threaded runtime, the ticker only runs if the get doesn't block it
+        assert_eq!(adapter.get("key").await.unwrap(), "value");
+        ticker.abort();
+        assert!(ticks.load(std::sync::atomic::Ordering::SeqCst) > 5);
+        assert!(adapter.exists("key").await.unwrap());
+        adapter.delete("key").await.unwrap();
+        assert!(adapter.list(None::<&str>).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn list_to_sender_delivers_every_key() {
+        let server = MockServer::start();
+        for i in 0..100 {
+            server.insert(&format!("key/{:03}", i), "value");
+        }
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        let receiver = std::thread::spawn(move || rx.iter().collect::<std::vec::Vec<String>>());
+        Synchronous::list_to_sender(&server.database(), Some("key/"), tx).unwrap();
+        assert_eq!(receiver.join().unwrap(), server.keys());
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        drop(rx);
+        Synchronous::list_to_sender(&server.database(), Some("key/"), tx).unwrap();
+    }
+
+    #[tokio::test]
+    async fn async_list_to_sender_delivers_every_key() {
+        let server = MockServer::start();
+        for i in 0..100 {
+            server.insert(&format!("key/{:03}", i), "value");
+        }
+        let db = server.database();
+        let (tx, rx) = futures::channel::mpsc::channel(1);
+        let receive = rx.collect::<std::vec::Vec<String>>();
+        let (res, keys) =
+            tokio::join!(Asynchronous::list_to_sender(&db, Some("key/"), tx), receive);
+        res.unwrap();
+        assert_eq!(keys, server.keys());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn max_concurrent_requests_bounds_requests_in_flight() {
+        let server = MockServer::start();
+        for i in 0..12 {
+            server.insert(&i.to_string(), "value");
+        }
+        let gate = server.hold("GET /");
+        let db = Database::new(server.config().with_max_concurrent_requests(Some(3)));
+        let gets = tokio::spawn(async move {
+            let keys: std::vec::Vec<String> = (0..12).map(|i| i.to_string()).collect();
+            let gets = keys.iter().map(|key| Asynchronous::get(&db, key.as_str()));
+            return futures::future::join_all(gets).await;
+        });
+        // each released request lets exactly one more in
+        tokio::task::spawn_blocking(move || {
+            gate.wait_arrivals(3);
+            for released in 1..=9 {
+                gate.release(1);
+                gate.wait_arrivals(3 + released);
+            }
+            gate.release_all();
+        })
+        .await
+        .unwrap();
+        for value in gets.await.unwrap() {
+            assert_eq!(value.unwrap(), "value");
+        }
+        assert_eq!(server.state().max_in_flight, 3);
+    }
+
+    #[test]
+    fn trim_trailing_newline_strips_a_single_newline() {
+        let server = MockServer::start();
+        server.insert("unix", "value\n");
+        server.insert("windows", "value\r\n");
+        server.insert("double", "value\n\n");
+        let db = Database::new(server.config().with_trim_trailing_newline(true));
+        assert_eq!(Synchronous::get(&db, "unix").unwrap(), "value");
+        assert_eq!(Synchronous::get(&db, "windows").unwrap(), "value");
+        assert_eq!(Synchronous::get(&db, "double").unwrap(), "value\n");
+        assert_eq!(
+            Synchronous::get(&server.database(), "unix").unwrap(),
+            "value\n"
+        );
+    }
+
+    #[test]
+    fn consistency_check_reports_keys_missing_on_get() {
+        let server = MockServer::start();
+        server.insert("a", "1");
+        server.insert("b", "2");
+        server.insert("c", "3");
+        let db = server.database();
+        assert_eq!(
+            Synchronous::consistency_check(&db, None::<&str>).unwrap(),
+            ConsistencyReport {
+                listed: 3,
+                missing_on_get: vec![],
+            }
+        );
+        server.state().overrides.insert("HEAD /c".to_string(), 404);
+        server.state().overrides.insert("HEAD /a".to_string(), 404);
+        assert_eq!(
+            Synchronous::consistency_check(&db, None::<&str>).unwrap(),
+            ConsistencyReport {
+                listed: 3,
+                missing_on_get: vec!["a".to_string(), "c".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn sanitize_keys_rewrites_every_key() {
+        let server = MockServer::start();
+        fn underscores(key: &str) -> String {
+            return key.replace(' ', "_");
+        }
+        let db = Database::new(server.config().with_sanitize_keys(Some(underscores)));
+        Synchronous::set(&db, "a b", "value").unwrap();
+        assert_eq!(server.keys(), vec!["a_b"]);
+        assert_eq!(db.stored_key("a b"), "a_b");
+        assert_eq!(Synchronous::get(&db, "a b").unwrap(), "value");
+        let receipt = Synchronous::set_with_receipt(&db, "c d", "value").unwrap();
+        assert_eq!(receipt.key, "c_d");
+        Synchronous::delete(&db, "a b").unwrap();
+        assert_eq!(server.keys(), vec!["c_d"]);
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn get_shared_hands_out_the_same_allocation() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("config", "value");
+        let first = Synchronous::get_shared(&db, "config").unwrap();
+        let second = Synchronous::get_shared(&db.clone(), "config").unwrap();
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+        assert_eq!(server.count("GET /config"), 1);
+        Synchronous::set(&db, "config", "changed").unwrap();
+        let third = Synchronous::get_shared(&db, "config").unwrap();
+        assert_eq!(&*third, "changed");
+        assert_eq!(&*first, "value");
+        assert_eq!(server.count("GET /config"), 2);
+    }
+
+    #[test]
+    fn delete_where_deletes_matching_values() {
+        let server = MockServer::start();
+        server.insert("session/1", "stale");
+        server.insert("session/2", "fresh");
+        server.insert("session/3", "stale");
+        server.insert("other", "stale");
+        let deleted =
+            Synchronous::delete_where(&server.database(), Some("session/"), |_, value| {
+                return value == "stale";
+            })
+            .unwrap();
+        assert_eq!(deleted, 2);
+        assert_eq!(server.keys(), vec!["other", "session/2"]);
+    }
+
+    #[tokio::test]
+    async fn async_delete_where_deletes_matching_values() {
+        let server = MockServer::start();
+        for i in 0..20 {
+            let value = if i % 2 == 0 { "stale" } else { "fresh" };
+            server.insert(&format!("session/{:02}", i), value);
+        }
+        let deleted = Asynchronous::delete_where(&server.database(), None::<&str>, |key, value| {
+            return key.starts_with("session/") && value == "stale";
+        })
+        .await
+        .unwrap();
+        assert_eq!(deleted, 10);
+        assert_eq!(server.keys().len(), 10);
+        assert!(server
+            .keys()
+            .iter()
+            .all(|key| server.value(key).as_deref() == Some("fresh")));
+    }
+
+    #[test]
+    fn get_nonempty_retry_retries_empty_values() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("key", "value");
+        server.state().empty_gets = 2;
+        assert_eq!(
+            Synchronous::get_nonempty_retry(&db, "key", 2).unwrap(),
+            "value"
+        );
+        assert_eq!(server.count("GET /key"), 3);
+        server.state().empty_gets = 2;
+        assert_eq!(Synchronous::get_nonempty_retry(&db, "key", 1).unwrap(), "");
+        let res = Synchronous::get_nonempty_retry(&db, "missing", 3);
+        assert!(matches!(error_kind(res), ErrorKind::NoItemFoundError));
+        assert_eq!(server.count("GET /missing"), 1);
+    }
+
+    #[test]
+    fn set_many_splits_bodies_over_max_body_bytes() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_max_body_bytes(Some(350)));
+        let pairs: std::vec::Vec<(String, String)> = (0..10)
+            .map(|i| (format!("k{}", i), "x".repeat(100)))
+            .collect();
+        Synchronous::set_many(&db, &pairs).unwrap();
+        // each encoded pair is 103 bytes, so 3 of them fit in a body
+        assert_eq!(server.count("POST /"), 4);
+        assert_eq!(server.keys().len(), 10);
+        assert!(server
+            .keys()
+            .iter()
+            .all(|key| server.value(key).unwrap().len() == 100));
+        let res = Synchronous::set_many(&db, &[("small", "x"), ("big", &"x".repeat(400))]);
+        assert!(matches!(error_kind(res), ErrorKind::ValueTooLargeError));
+        assert_eq!(server.count("POST /"), 4);
+    }
+
+    #[test]
+    fn set_many_checks_and_records_every_key() {
+        let server = MockServer::start();
+        let events = std::sync::Arc::new(std::sync::Mutex::new(std::vec::Vec::new()));
+        let after = events.clone();
+        let cooldown = std::time::Duration::from_secs(60);
+        let db = Database::new(server.config().with_circuit_breaker(Some((1, cooldown))))
+            .with_operation_hook(
+                |_, _| {},
+                move |_, key, status| after.lock().unwrap().push(format!("{} {:?}", key, status)),
+            );
+        server.state().hang_up = true;
+        assert!(Synchronous::set_many(&db, &[("a", "1"), ("b", "2")]).is_err());
+        server.state().hang_up = false;
+        let res = Synchronous::set(&db, "b", "3");
+        assert!(matches!(error_kind(res), ErrorKind::CircuitOpenError));
+        let res = Synchronous::set_many(&db, &[("c", "4"), ("b", "5")]);
+        assert!(matches!(error_kind(res), ErrorKind::CircuitOpenError));
+        assert!(server.requests().is_empty());
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["a None", "b None", "b None", "c None", "b None"]
+        );
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn set_many_invalidates_cached_keys_even_when_failing() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("a", "1");
+        assert_eq!(&*Synchronous::get_shared(&db, "a").unwrap(), "1");
+        server.state().hang_up = true;
+        let res = Synchronous::set_many(&db, &[("a", "2")]);
+        assert!(matches!(error_kind(res), ErrorKind::HttpError));
+        server.state().hang_up = false;
+        server.insert("a", "maybe written");
+        assert_eq!(
+            &*Synchronous::get_shared(&db, "a").unwrap(),
+            "maybe written"
+        );
+        Synchronous::set_many(&db, &[("a", "3")]).unwrap();
+        assert_eq!(&*Synchronous::get_shared(&db, "a").unwrap(), "3");
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn auto_decompress_reads_back_compressed_values() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_auto_decompress(true));
+        let value = "a".repeat(100_000);
+        Synchronous::set_compressed(&db, "big", &value).unwrap();
+        let stored = server.value("big").unwrap();
+        assert!(stored.starts_with(COMPRESSED_VALUE_PREFIX));
+        assert!(stored.len() < 1000);
+        assert_eq!(Synchronous::get(&db, "big").unwrap(), value);
+        assert_eq!(Synchronous::get(&server.database(), "big").unwrap(), stored);
+        Synchronous::set(&db, "plain", "not compressed").unwrap();
+        assert_eq!(Synchronous::get(&db, "plain").unwrap(), "not compressed");
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn auto_decompress_stops_at_max_value_bytes() {
+        let server = MockServer::start();
+        let db = Database::new(
+            server
+                .config()
+                .with_auto_decompress(true)
+                .with_max_value_bytes(Some(20_000)),
+        );
+        // small enough to be stored, but expands to 10 MB
+        Synchronous::set_compressed(&db, "bomb", "a".repeat(10_000_000)).unwrap();
+        let res = Synchronous::get(&db, "bomb");
+        assert!(matches!(error_kind(res), ErrorKind::ValueTooLargeError));
+        Synchronous::set_compressed(&db, "fits", "a".repeat(20_000)).unwrap();
+        assert_eq!(Synchronous::get(&db, "fits").unwrap(), "a".repeat(20_000));
+    }
+
+    #[test]
+    fn measure_write_visibility_waits_for_lagging_writes() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.state().write_lag = std::time::Duration::from_millis(200);
+        let lag = Synchronous::measure_write_visibility(&db, std::time::Duration::from_secs(5));
+        let lag = lag.unwrap();
+        assert!(lag >= std::time::Duration::from_millis(150), "{:?}", lag);
+        assert!(lag < std::time::Duration::from_secs(5), "{:?}", lag);
+        assert!(server.keys().is_empty());
+        server.state().write_lag = std::time::Duration::from_secs(60);
+        let res = Synchronous::measure_write_visibility(&db, std::time::Duration::from_millis(100));
+        assert!(matches!(error_kind(res), ErrorKind::TimeoutError));
+    }
+
+    #[tokio::test]
+    async fn list_page_resumes_from_stored_cursor_tokens() {
+        let server = MockServer::start();
+        let db = server.database();
+        for i in 0..7 {
+            server.insert(&format!("key/{}", i), "value");
+        }
+        let mut seen = std::vec::Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let cursor = token.as_deref().map(|t| ListCursor::from_token(t).unwrap());
+            let (page, next) = Asynchronous::list_page(&db, Some("key/"), cursor.as_ref(), 3)
+                .await
+                .unwrap();
+            assert!(page.len() <= 3);
+            seen.extend(page);
+            if seen.len() == 3 {
+                // behind the cursor, skipped by this scan
+                server.insert("key/0a", "value");
+            }
+            match next {
+                Some(next) => token = Some(next.to_token()),
+                None => break,
+            }
+        }
+        let expected: std::vec::Vec<String> = (0..7).map(|i| format!("key/{}", i)).collect();
+        assert_eq!(seen, expected);
+        let res = ListCursor::from_token("garbage");
+        assert!(matches!(error_kind(res), ErrorKind::DecodeError));
+        let requests = server.requests().len();
+        let res = Asynchronous::list_page(&db, Some("key/"), None, 0).await;
+        assert!(matches!(error_kind(res), ErrorKind::InvalidArgumentError));
+        assert_eq!(server.requests().len(), requests);
+    }
+
+    #[test]
+    fn set_idempotent_applies_a_write_once() {
+        let server = MockServer::start();
+        let db = server.database();
+        assert!(Synchronous::set_idempotent(&db, "key", "first", Some("request-1")).unwrap());
+        assert!(!Synchronous::set_idempotent(&db, "key", "retried", Some("request-1")).unwrap());
+        assert_eq!(server.value("key").as_deref(), Some("first"));
+        assert_eq!(server.count("POST /"), 2); // the value and its sidecar key
+        assert!(Synchronous::set_idempotent(&db, "key", "second", Some("request-2")).unwrap());
+        assert!(Synchronous::set_idempotent(&db, "key", "third", None).unwrap());
+        assert_eq!(server.value("key").as_deref(), Some("third"));
+        let sidecars = Synchronous::list(&db, Some(format!("{}idempotency/", RESERVED_PREFIX)));
+        assert_eq!(sidecars.unwrap().len(), 2);
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn health_service_responds_with_ping_status() {
+        use tower_service::Service;
+        let server = MockServer::start();
+        let mut service = server.database().health_service();
+        let ready = futures::future::poll_fn(|cx| Service::<()>::poll_ready(&mut service, cx));
+        ready.await.unwrap();
+        let status = service.call(()).await.unwrap();
+        assert!(matches!(status, PingStatus::Reachable));
+        assert!(status.is_healthy());
+        server.state().status = Some(401);
+        let status = service.call("any request").await.unwrap();
+        assert!(matches!(status, PingStatus::Unauthorized));
+        assert!(!status.is_healthy());
+    }
+
+    #[test]
+    fn circuit_breaker_opens_and_closes() {
+        let server = MockServer::start();
+        server.insert("key", "value");
+        server.insert("other", "value");
+        let cooldown = std::time::Duration::from_secs(10);
+        let db = Database::new(server.config().with_circuit_breaker(Some((2, cooldown))));
+        server.state().overrides.insert("GET /key".to_string(), 500);
+        assert!(Synchronous::get(&db, "key").is_err());
+        assert!(Synchronous::get(&db, "key").is_err());
+        let res = Synchronous::get(&db, "key");
+        assert!(matches!(error_kind(res), ErrorKind::CircuitOpenError));
+        assert_eq!(server.count("GET /key"), 2);
+        assert_eq!(Synchronous::get(&db, "other").unwrap(), "value");
+        // a failed probe opens it again
+        end_cooldowns(&db, cooldown);
+        assert!(!matches!(
+            error_kind(Synchronous::get(&db, "key")),
+            ErrorKind::CircuitOpenError
+        ));
+        let res = Synchronous::get(&db, "key");
+        assert!(matches!(error_kind(res), ErrorKind::CircuitOpenError));
+        assert_eq!(server.count("GET /key"), 3);
+        // a successful probe closes it
+        server.state().overrides.clear();
+        end_cooldowns(&db, cooldown);
+        assert_eq!(Synchronous::get(&db, "key").unwrap(), "value");
+        assert_eq!(Synchronous::get(&db, "key").unwrap(), "value");
+        assert_eq!(server.count("GET /key"), 5);
+    }
+
+    /// Make every open circuit of `db` look like it was opened a `cooldown` ago, instead of waiting for it.
+    fn end_cooldowns(db: &Database, cooldown: std::time::Duration) {
+        for circuit in db.circuits.lock().unwrap().values_mut() {
+            circuit.opened_at = circuit
+                .opened_at
+                .map(|opened_at| opened_at.checked_sub(cooldown).unwrap());
+        }
+    }
+
+    #[test]
+    fn circuit_breaker_tracks_a_bounded_number_of_keys() {
+        let server = MockServer::start();
+        let cooldown = std::time::Duration::from_secs(60);
+        let db = Database::new(server.config().with_circuit_breaker(Some((1, cooldown))));
+        // same as that many failed requests, without sending them
+        for i in 0..=MAX_CIRCUITS {
+            db.circuit_record(&format!("key/{}", i), None);
+        }
+        assert_eq!(db.circuits.lock().unwrap().len(), MAX_CIRCUITS);
+        server.state().hang_up = true;
+        // the oldest one got forgotten
+        let res = Synchronous::get(&db, "key/0");
+        assert!(matches!(error_kind(res), ErrorKind::HttpError));
+        let res = Synchronous::get(&db, format!("key/{}", MAX_CIRCUITS));
+        assert!(matches!(error_kind(res), ErrorKind::CircuitOpenError));
+    }
+
+    #[test]
+    fn ping_bypasses_the_circuit_breaker() {
+        let server = MockServer::start();
+        let cooldown = std::time::Duration::from_secs(60);
+        let db = Database::new(server.config().with_circuit_breaker(Some((1, cooldown))));
+        server.state().status = Some(500);
+        assert_eq!(Synchronous::ping(&db), Synchronous::ping(&db));
+        assert_eq!(server.requests().len(), 2);
+        assert!(db.circuits.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_into_sink_feeds_every_key() {
+        let server = MockServer::start();
+        server.insert("user/1", "alice");
+        server.insert("user/2", "bob");
+        server.insert("other", "skipped");
+        let mut keys: std::vec::Vec<String> = std::vec::Vec::new();
+        Asynchronous::list_into_sink(&server.database(), Some("user/"), &mut keys)
+            .await
+            .unwrap();
+        assert_eq!(keys, vec!["user/1", "user/2"]);
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        drop(rx);
+        let res = Asynchronous::list_into_sink(&server.database(), Some("user/"), tx).await;
+        assert!(matches!(error_kind(res), ErrorKind::SinkError));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn set_rejects_values_colliding_with_compressed_format() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_auto_decompress(true));
+        let compressed = compress_value("hello").unwrap();
+        let res = Synchronous::set(&db, "key", &compressed);
+        assert!(matches!(error_kind(res), ErrorKind::ReservedValueError));
+        let res = Synchronous::set_many(&db, &[("key", &compressed)]);
+        assert!(matches!(error_kind(res), ErrorKind::ReservedValueError));
+        assert!(server.requests().is_empty());
+        let lookalike = format!("{}but not base64!", COMPRESSED_VALUE_PREFIX);
+        Synchronous::set(&db, "key", &lookalike).unwrap();
+        assert_eq!(server.value("key"), Some(lookalike));
+        // nothing gets decompressed without auto_decompress
+        Synchronous::set(&server.database(), "key", &compressed).unwrap();
+        assert_eq!(server.value("key"), Some(compressed));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn set_rejects_compressed_values_decompressing_past_the_check_limit() {
+        let server = MockServer::start();
+        let db = Database::new(server.config().with_auto_decompress(true));
+        let bomb = compress_value(&"a".repeat(MAX_CHECKED_DECOMPRESSED_BYTES + 1)).unwrap();
+        assert!(bomb.len() < 1024 * 1024);
+        let res = Synchronous::set(&db, "key", &bomb);
+        assert!(matches!(error_kind(res), ErrorKind::ReservedValueError));
+        let fits = compress_value(&"a".repeat(MAX_CHECKED_DECOMPRESSED_BYTES)).unwrap();
+        assert!(looks_compressed(&fits, None));
+        assert!(server.requests().is_empty());
+        // characters split between chunks are still UTF-8, bytes that aren't are passed through when read back
+        let split = compress_value(&format!("{}\u{e9}", "a".repeat(8191)).repeat(4)).unwrap();
+        assert!(looks_compressed(&split, None));
+        let mut encoder =
+            flate2::write::GzEncoder::new(std::vec::Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &[b'a', 0xff]).unwrap();
+        let invalid = base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            encoder.finish().unwrap(),
+        );
+        assert!(!looks_compressed(&invalid, None));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_values_can_be_copied() {
+        let source = MockServer::start();
+        let dest = MockServer::start();
+        let db = source.database();
+        Synchronous::set_compressed(&db, "app/big", "a".repeat(10_000)).unwrap();
+        assert_eq!(
+            Synchronous::clone_namespace(&db, "app/", "backup/").unwrap(),
+            1
+        );
+        assert_eq!(source.value("backup/big"), source.value("app/big"));
+        let copied = Synchronous::copy_all_to(&db, &dest.database(), None::<&str>, false).unwrap();
+        assert_eq!(copied, 2);
+        assert_eq!(dest.value("app/big"), source.value("app/big"));
+        let reader = Database::new(dest.config().with_auto_decompress(true));
+        assert_eq!(
+            Synchronous::get(&reader, "backup/big").unwrap(),
+            "a".repeat(10_000)
+        );
+    }
+
+    #[test]
+    fn database_new_accepts_url_strings() {
+        let server = MockServer::start();
+        server.insert("key", "value");
+        let db = Database::new(server.url.as_str());
+        assert_eq!(Synchronous::get(&db, "key").unwrap(), "value");
+        let db = Database::new(server.url.clone());
+        assert_eq!(Synchronous::get(&db, "key").unwrap(), "value");
+        let res = Config::try_new_custom_url("ftp://example.com/secret-token");
+        assert!(matches!(error_kind(res), ErrorKind::InvalidUrlError));
+        let res = Config::try_new_custom_url("secret-token");
+        assert!(matches!(error_kind(res), ErrorKind::InvalidUrlError));
+    }
+
+    #[test]
+    fn database_new_panics_on_invalid_urls_without_leaking_them() {
+        let res = std::panic::catch_unwind(|| Database::new("https://secret-token host"));
+        let message = res.err().unwrap().downcast::<String>().unwrap();
+        assert!(message.contains("Database URL is invalid"));
+        assert!(!message.contains("secret-token"));
+    }
+
+    #[test]
+    fn list_checked_tells_explicit_from_ambiguous_empty() {
+        let server = MockServer::start();
+        let db = server.database();
+        server.insert("user/1", "alice");
+        let listing = Synchronous::list_checked(&db, Some("user/")).unwrap();
+        assert_eq!(listing.keys, vec!["user/1"]);
+        assert!(!listing.explicit_empty && !listing.is_ambiguous_empty());
+        // an empty 200, which is all Replit ever sends
+        let listing = Synchronous::list_checked(&db, Some("nobody/")).unwrap();
+        assert!(listing.keys.is_empty());
+        assert!(!listing.explicit_empty && listing.is_ambiguous_empty());
+        server.state().status = Some(204);
+        let listing = Synchronous::list_checked(&db, Some("nobody/")).unwrap();
+        assert!(listing.keys.is_empty());
+        assert!(listing.explicit_empty && !listing.is_ambiguous_empty());
+    }
+
+    #[tokio::test]
+    async fn async_list_checked_tells_explicit_from_ambiguous_empty() {
+        let server = MockServer::start();
+        let db = server.database();
+        let listing = Asynchronous::list_checked(&db, None::<&str>).await.unwrap();
+        assert!(listing.is_ambiguous_empty());
+        server.state().status = Some(204);
+        let listing = Asynchronous::list_checked(&db, None::<&str>).await.unwrap();
+        assert!(listing.explicit_empty);
+    }
 }